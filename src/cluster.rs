@@ -0,0 +1,211 @@
+use std::path::Path;
+
+use crate::runner::{Input, Job};
+
+/// Options of generated cluster job scripts.
+#[derive(Debug, Clone)]
+pub struct ScriptOptions {
+    /// Solver command line. The split file replaces "{}", or is
+    /// appended if there is none.
+    pub solver: Vec<String>,
+    /// The runlim binary, which writes the logs merging reads.
+    pub runlim: String,
+    /// Wall clock timeout of each job in seconds.
+    pub timeout: Option<f64>,
+    /// Memory limit of each job in MB.
+    pub memory_limit: Option<u64>,
+    /// CPU time limit of each job in seconds.
+    pub cpu_limit: Option<u64>,
+    /// Slurm partition to submit to.
+    pub partition: Option<String>,
+    /// Maximum number of array tasks running at the same time.
+    pub max_parallel: Option<usize>,
+}
+
+// Quotes an argument for sh, unless it only consists of safe characters.
+fn quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+// The runlim command line solving the input, with already quoted input.
+fn runlim_command(options: &ScriptOptions, input: &str) -> String {
+    let mut args = vec![quote(&options.runlim)];
+    if let Some(t) = options.timeout {
+        args.push(format!("--real-time-limit={}", t.ceil() as u64));
+    }
+    if let Some(t) = options.cpu_limit {
+        args.push(format!("--time-limit={}", t));
+    }
+    if let Some(m) = options.memory_limit {
+        args.push(format!("--space-limit={}", m));
+    }
+    // The input is quoted already, so that it can be a shell variable.
+    // It replaces "{}", or is appended if there is none.
+    let mut replaced = false;
+    for a in options.solver.iter() {
+        let parts: Vec<String> = a
+            .split("{}")
+            .map(|p| {
+                if p.is_empty() {
+                    String::new()
+                } else {
+                    quote(p)
+                }
+            })
+            .collect();
+        replaced |= parts.len() > 1;
+        args.push(parts.join(input));
+    }
+    if !replaced {
+        args.push(input.to_owned());
+    }
+    args.join(" ")
+}
+
+// Runs the solver, logging to log. runlim exits with the exit code of
+// the solver, which merging expects in the format of GNU time.
+fn job_command(options: &ScriptOptions, input: &str, log: &str) -> String {
+    format!(
+        "{} > {} 2>&1; code=$?; if [ $code -ne 0 ]; then echo \"Command exited with non-zero status $code\" >> {}; fi",
+        runlim_command(options, input),
+        log,
+        log
+    )
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_str().unwrap().to_owned()
+}
+
+// The written split file of the job, as jobs run on the cluster.
+fn input_name(job: &Job) -> Result<String, String> {
+    match &job.input {
+        Input::File(path) => Ok(file_name(path)),
+        Input::Split { .. } => Err(format!("Split {} was never written", job.id)),
+    }
+}
+
+/// A GNU parallel job list, one line per split, to be run in the
+/// working directory, e.g. `parallel -j 8 < jobs.txt`. The splits have
+/// to be written.
+pub fn parallel_jobs(jobs: &[Job], options: &ScriptOptions) -> Result<String, String> {
+    jobs.iter()
+        .map(|j| {
+            Ok(job_command(
+                options,
+                &quote(&input_name(j)?),
+                &quote(&file_name(&j.logs[0])),
+            ) + "\n")
+        })
+        .collect()
+}
+
+/// A Slurm array job script with one task per split, running in the
+/// given working directory. Requires at least one job, the splits have
+/// to be written.
+pub fn slurm_script(
+    jobs: &[Job],
+    options: &ScriptOptions,
+    name: &str,
+    cwd: &Path,
+) -> Result<String, String> {
+    let mut s = String::from("#!/bin/bash\n");
+    s.push_str(&format!("#SBATCH --job-name={}\n", quote(name)));
+    s.push_str(&format!(
+        "#SBATCH --array=0-{}{}\n",
+        jobs.len() - 1,
+        options
+            .max_parallel
+            .map_or(String::new(), |n| format!("%{}", n))
+    ));
+    s.push_str("#SBATCH --ntasks=1\n#SBATCH --cpus-per-task=1\n");
+    // Some slack, so that runlim hits its limits first and writes the log.
+    let seconds = options
+        .timeout
+        .map(|t| t.ceil() as u64)
+        .into_iter()
+        .chain(options.cpu_limit)
+        .max();
+    if let Some(seconds) = seconds {
+        s.push_str(&format!("#SBATCH --time={}\n", seconds.div_ceil(60) + 1));
+    }
+    if let Some(m) = options.memory_limit {
+        s.push_str(&format!("#SBATCH --mem={}M\n", m + m / 10 + 100));
+    }
+    if let Some(p) = &options.partition {
+        s.push_str(&format!("#SBATCH --partition={}\n", quote(p)));
+    }
+    s.push_str("#SBATCH --output=/dev/null\n\n");
+    s.push_str(&format!(
+        "cd {} || exit 1\n\n",
+        quote(cwd.to_str().unwrap())
+    ));
+    s.push_str("SPLITS=(\n");
+    for j in jobs.iter() {
+        s.push_str(&format!(
+            "  {} {}\n",
+            quote(&input_name(j)?),
+            quote(&file_name(&j.logs[0]))
+        ));
+    }
+    s.push_str(")\n");
+    s.push_str("SPLIT=\"${SPLITS[$((2 * SLURM_ARRAY_TASK_ID))]}\"\n");
+    s.push_str("LOG=\"${SPLITS[$((2 * SLURM_ARRAY_TASK_ID + 1))]}\"\n\n");
+    s.push_str(&job_command(options, "\"$SPLIT\"", "\"$LOG\""));
+    s.push('\n');
+    Ok(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_job_scripts() {
+        let jobs: Vec<Job> = ["0", "1"]
+            .iter()
+            .map(|id| Job {
+                id: id.to_string(),
+                input: Input::File(PathBuf::from(format!("/w/{}:f.qdimacs", id))),
+                logs: vec![PathBuf::from(format!("/w/kissat-{}:f.qdimacs.log", id))],
+            })
+            .collect();
+        let options = ScriptOptions {
+            solver: vec!["kissat".to_string(), "--seed=1".to_string()],
+            runlim: "runlim".to_string(),
+            timeout: Some(600.0),
+            memory_limit: Some(8000),
+            cpu_limit: None,
+            partition: None,
+            max_parallel: Some(10),
+        };
+        let parallel = parallel_jobs(&jobs, &options).unwrap();
+        assert_eq!(
+            parallel.lines().next().unwrap(),
+            "runlim --real-time-limit=600 --space-limit=8000 kissat --seed=1 0:f.qdimacs \
+             > kissat-0:f.qdimacs.log 2>&1; code=$?; if [ $code -ne 0 ]; then echo \
+             \"Command exited with non-zero status $code\" >> kissat-0:f.qdimacs.log; fi"
+        );
+        let slurm = slurm_script(&jobs, &options, "kissat", Path::new("/w")).unwrap();
+        assert!(slurm.contains("#SBATCH --array=0-1%10\n"));
+        assert!(slurm.contains("#SBATCH --time=11\n"));
+        assert!(slurm.contains("  1:f.qdimacs kissat-1:f.qdimacs.log\n"));
+        assert!(slurm.contains("kissat --seed=1 \"$SPLIT\" > \"$LOG\" 2>&1"));
+        assert_eq!(quote("it's"), "'it'\\''s'");
+
+        let options = ScriptOptions {
+            solver: vec!["caqe".to_string(), "--in={}".to_string()],
+            ..options
+        };
+        assert_eq!(
+            runlim_command(&options, "\"$SPLIT\""),
+            "runlim --real-time-limit=600 --space-limit=8000 caqe --in=\"$SPLIT\""
+        );
+    }
+}
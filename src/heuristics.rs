@@ -0,0 +1,98 @@
+use crate::propagation::Propagator;
+use crate::Formula;
+
+/// Heuristic used to select (and order) the variables to split on.
+#[derive(Debug, Clone, Copy, PartialEq, Default, strum_macros::Display, clap::ValueEnum)]
+pub enum SplitHeuristic {
+    /// Split in the order of the quantifier prefix.
+    #[default]
+    Prefix,
+    /// Prefer variables occurring in many clauses.
+    Occurrence,
+    /// Two-sided Jeroslow-Wang score, prefers variables in short clauses.
+    JeroslowWang,
+    /// Propagate both polarities and prefer variables that reduce the
+    /// formula the most in both branches.
+    Lookahead,
+}
+
+fn occurrence_score(propagator: &Propagator, v: i32) -> f64 {
+    (propagator.occurrences(v).len() + propagator.occurrences(-v).len()) as f64
+}
+
+fn jeroslow_wang_score(formula: &Formula, propagator: &Propagator, v: i32) -> f64 {
+    propagator
+        .occurrences(v)
+        .iter()
+        .chain(propagator.occurrences(-v).iter())
+        .map(|c| 2.0_f64.powi(-(formula.matrix[*c].len() as i32)))
+        .sum()
+}
+
+fn lookahead_score(formula: &Formula, propagator: &Propagator, root: &[i32], v: i32) -> f64 {
+    // Variables fixed without any decision are useless to split on.
+    if root.iter().any(|l| l.abs() == v) {
+        return 0.0;
+    }
+    // A failed literal is counted as if it assigned everything, as that
+    // branch is immediately refuted.
+    let reduction = |lit: i32| -> f64 {
+        match propagator.propagate(&[lit]) {
+            Some(trail) => (trail.len() - root.len() - 1) as f64,
+            None => formula.nr_of_variables as f64,
+        }
+    };
+    let pos = reduction(v);
+    let neg = reduction(-v);
+    // Mixing function from march, balances both branches.
+    1024.0 * pos * neg + pos + neg
+}
+
+/// Scores the given variables, higher is better.
+pub fn score_variables(formula: &Formula, heuristic: SplitHeuristic, vars: &[i32]) -> Vec<f64> {
+    let propagator = Propagator::new(formula);
+    let root = propagator.propagate(&[]).unwrap_or_default();
+    vars.iter()
+        .map(|v| {
+            let v = v.abs();
+            match heuristic {
+                SplitHeuristic::Prefix => 0.0,
+                SplitHeuristic::Occurrence => occurrence_score(&propagator, v),
+                SplitHeuristic::JeroslowWang => jeroslow_wang_score(formula, &propagator, v),
+                SplitHeuristic::Lookahead => lookahead_score(formula, &propagator, &root, v),
+            }
+        })
+        .collect()
+}
+
+/// Orders the given variables by descending score. Ties keep their
+/// original order, so SplitHeuristic::Prefix keeps the input order.
+pub fn order_variables(formula: &Formula, heuristic: SplitHeuristic, vars: &[i32]) -> Vec<i32> {
+    let scores = score_variables(formula, heuristic, vars);
+    let mut order: Vec<usize> = (0..vars.len()).collect();
+    order.sort_by(|a, b| scores[*b].partial_cmp(&scores[*a]).unwrap());
+    order.into_iter().map(|i| vars[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_qdimacs;
+
+    #[test]
+    fn test_order_variables() {
+        let f = parse_qdimacs("p cnf 3 4\n1 2 0\n-2 3 0\n2 -3 1 0\n3 0\n", false).unwrap();
+        assert_eq!(
+            order_variables(&f, SplitHeuristic::Occurrence, &[1, 2, 3]),
+            vec![2, 3, 1]
+        );
+        assert_eq!(
+            order_variables(&f, SplitHeuristic::JeroslowWang, &[1, 2, 3]),
+            vec![3, 2, 1]
+        );
+        assert_eq!(
+            order_variables(&f, SplitHeuristic::Prefix, &[1, 2, 3]),
+            vec![1, 2, 3]
+        );
+    }
+}
@@ -2,33 +2,193 @@ extern crate pest;
 use bitvec::prelude::*;
 #[macro_use]
 extern crate pest_derive;
-use lazy_static::lazy_static;
 use std::path::{Path, PathBuf};
 
 use pest::Parser;
 use regex::Regex;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::io::BufWriter;
+use strum::IntoEnumIterator;
+
+pub mod cluster;
+pub mod heuristics;
+pub mod logs;
+pub mod merge;
+pub mod propagation;
+pub mod runner;
+pub mod statistics;
+pub mod strategy;
+pub mod xor;
+
+use heuristics::SplitHeuristic;
+use logs::{parse_log, LogFile, LogFormat, LogPattern};
+use merge::SplitNode;
+use propagation::Propagator;
+pub use xor::XorSplit;
 
 #[derive(Parser)]
 #[grammar = "qdimacs.pest"]
 struct QDIMACSParser;
 
-#[derive(Debug, Clone, Copy, PartialEq, strum_macros::Display)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    strum_macros::Display,
+    strum_macros::EnumString,
+    strum_macros::EnumIter,
+    serde::Serialize,
+)]
 pub enum SolverReturnCode {
     Sat,
     Unsat,
+    /// Ran out of (wall clock or CPU) time.
     Timeout,
+    /// Ran out of memory.
+    MemoryOut,
+    /// Killed by a signal, e.g. a segmentation fault.
+    Crashed,
+    /// Exited with an exit code other than 10 or 20.
+    Error,
+    /// There is no log, i.e. the job never ran.
+    Missing,
+    /// Finished without an answer, or the log is incomplete.
+    Unknown,
+    /// Stopped by the runner, as the result no longer mattered.
+    Cancelled,
+    /// The answer printed by the solver contradicts its exit code.
+    Mismatch,
+}
+
+impl SolverReturnCode {
+    /// Whether the result is known, i.e. Sat or Unsat.
+    pub fn is_decided(&self) -> bool {
+        matches!(self, SolverReturnCode::Sat | SolverReturnCode::Unsat)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SolverResult {
     pub wall_seconds: f64,
     pub result: SolverReturnCode,
-    pub name: String
+    pub name: String,
+    /// User and system time, if known.
+    pub cpu_seconds: Option<f64>,
+    /// Peak resident set size, if known.
+    pub peak_memory_kb: Option<u64>,
+    pub job: JobInfo,
+    /// Outermost assignment printed by the solver, i.e. the QDO "V"
+    /// lines or the "v" lines of a model. Empty if there is none.
+    pub certificate: Vec<i32>,
+    /// Bounds on the time until the result is decided, for results
+    /// reduced from splits, see SolverResult::bounds.
+    pub bounds: Option<TimeBounds>,
+}
+
+/// Bounds on the wall clock time until a result is decided.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct TimeBounds {
+    pub lower: f64,
+    /// None if unbounded, e.g. as a split timed out.
+    pub upper: Option<f64>,
+}
+
+impl SolverResult {
+    /// The reduced bounds, or the ones of a single job: the time of a
+    /// decided job is known, an undecided one did not decide within its
+    /// wall time, e.g. the timeout, and might never decide.
+    pub fn bounds(&self) -> TimeBounds {
+        self.bounds.unwrap_or(TimeBounds {
+            lower: self.wall_seconds,
+            upper: self.result.is_decided().then_some(self.wall_seconds),
+        })
+    }
+
+    /// The result of a job without an answer of the given solver, e.g.
+    /// as its log is missing.
+    pub fn missing(name: &str) -> SolverResult {
+        SolverResult {
+            wall_seconds: 0.0,
+            result: SolverReturnCode::Missing,
+            name: name.to_owned(),
+            cpu_seconds: None,
+            peak_memory_kb: None,
+            job: JobInfo::default(),
+            certificate: vec![],
+            bounds: None,
+        }
+    }
+}
+
+/// Where and how a job ran, as far as its log tells.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobInfo {
+    /// runlim status, e.g. "ok" or "out of time".
+    pub status: Option<String>,
+    pub host: Option<String>,
+    /// Load of the host while running.
+    pub load: Option<f64>,
+    /// Header of submit.pl (or the run subcommand): name of the run,
+    /// task, commit of the solver and its additional arguments.
+    pub run_name: Option<String>,
+    pub task: Option<String>,
+    pub commit: Option<String>,
+    pub args: Option<String>,
+}
+
+/// Options deciding which splits are produced from a formula. Merging
+/// has to use the same options as splitting to reconstruct the splits.
+#[derive(Debug, Clone, Default)]
+pub struct SplitOptions {
+    pub depth: u32,
+    pub heuristic: SplitHeuristic,
+    pub probe: bool,
+    /// Universal quantifier blocks to expand instead of split.
+    pub expand: Vec<usize>,
+    /// Additional clause splits, given as their literals.
+    pub clause_splits: Vec<Vec<i32>>,
+    /// Number of random XOR constraints to split on.
+    pub xor: usize,
+    /// Variables of the XOR constraints, all outermost ones if empty.
+    pub xor_vars: Vec<i32>,
+    pub xor_seed: u64,
+    /// Additional depth of each generation of re-splitting timed out
+    /// splits.
+    pub resplit_depth: u32,
+    /// Fixed literals to split below.
+    pub under: Vec<i32>,
+}
+
+impl SplitOptions {
+    /// Checks that the depth fits the requested splits. The depth only
+    /// uses whole splits, a smaller one would silently drop the XOR
+    /// split.
+    pub fn check(&self) -> Result<(), String> {
+        if self.xor > self.depth as usize {
+            return Err(format!(
+                "--xor {} requires a --depth of at least {}",
+                self.xor, self.xor
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeResult {
+    /// Nothing is known, the split has to be solved.
+    Open,
+    /// Unit propagation refuted the assumptions, the split is Unsat.
+    Refuted,
+    /// A sibling on a universal layer was refuted, which decides the
+    /// parent. The result of this split does not matter.
+    Irrelevant,
 }
 
 #[derive(Debug, Clone)]
@@ -44,82 +204,386 @@ pub struct IntegerSplitConstraint {
     pub target: Vec<Vec<i32>>,
 }
 
-pub fn extract_result_from_file(path: &Path, name: &String) -> SolverResult {
-    lazy_static! {
-        static ref EXIT_CODE: Regex =
-            Regex::new("Command exited with non-zero status (\\d+)").unwrap();
-        static ref WALL_TIME: Regex =
-            Regex::new("^\\[runlim\\] real:\\s*(\\d+(?:\\.\\d+))").unwrap();
+/// Counts the results by their return code, e.g. "3 Sat, 1 Timeout".
+pub fn count_results<'a>(results: impl Iterator<Item = &'a SolverResult>) -> String {
+    let results: Vec<&SolverResult> = results.collect();
+    SolverReturnCode::iter()
+        .map(|code| (code, results.iter().filter(|r| r.result == code).count()))
+        .filter(|(_, n)| *n > 0)
+        .map(|(code, n)| format!("{} {}", n, code))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Classifies the outcome of a solver from its exit code and the
+/// runlim status. An answer always counts, even if a limit was hit
+/// while exiting.
+pub(crate) fn classify_result(exit_code: Option<i32>, status: Option<&str>) -> SolverReturnCode {
+    match (exit_code, status) {
+        (Some(10), _) => SolverReturnCode::Sat,
+        (Some(20), _) => SolverReturnCode::Unsat,
+        (_, Some("cancelled")) => SolverReturnCode::Cancelled,
+        (_, Some(s)) if s.starts_with("out of memory") => SolverReturnCode::MemoryOut,
+        // Older runlim versions only print "out".
+        (_, Some(s)) if s.starts_with("out") => SolverReturnCode::Timeout,
+        (_, Some(s)) if s.starts_with("signal") => SolverReturnCode::Crashed,
+        (_, Some(s)) if s != "ok" => SolverReturnCode::Unknown,
+        // Exit code of timeout(1).
+        (Some(124), _) => SolverReturnCode::Timeout,
+        // Shells report signals as 128 + signal.
+        (Some(c), _) if c > 128 => SolverReturnCode::Crashed,
+        (Some(c), _) if c != 0 => SolverReturnCode::Error,
+        _ => SolverReturnCode::Unknown,
     }
+}
 
-    let mut wall_seconds: f64 = 0.0;
-    let mut result: SolverReturnCode = SolverReturnCode::Timeout;
+/// Reads the result of a single job from its log, see logs::parse_log.
+/// The task is the name of the solved file. A missing log gives a
+/// Missing result.
+pub fn extract_result_from_file(
+    path: &Path,
+    name: &String,
+    task: &str,
+    format: LogFormat,
+) -> SolverResult {
+    let Ok(log) = fs::read(path) else {
+        return SolverResult::missing(name);
+    };
+    // Solver output is not necessarily valid UTF-8.
+    let info = parse_log(&String::from_utf8_lossy(&log), task, format);
+    SolverResult {
+        wall_seconds: info.wall_seconds.unwrap_or(0.0),
+        result: info.result(),
+        name: name.to_owned(),
+        cpu_seconds: info.cpu_seconds,
+        peak_memory_kb: info.peak_memory_kb,
+        job: JobInfo {
+            status: info.status.clone(),
+            ..info.job
+        },
+        certificate: info.certificate,
+        bounds: None,
+    }
+}
 
-    let f = File::open(path).unwrap();
-    let f = BufReader::new(f);
+// Follows the Simsala convention.
+pub(crate) fn log_path(orig_file: &Path, name: &str, id: &str, cwd: &Path) -> PathBuf {
+    let mut filename: String = String::new();
+    filename.push_str(name);
+    filename.push('-');
+    filename.push_str(id);
+    filename.push(':');
+    filename.push_str(orig_file.file_name().unwrap().to_str().unwrap());
+    filename.push_str(".log");
+    let mut p = PathBuf::new();
+    p.push(cwd);
+    p.push(filename);
+    p
+}
 
-    for line in f.lines() {
-        let line = line.unwrap();
-        let exit_code = EXIT_CODE.captures(&line).and_then(|c| {
-            c.get(1).and_then(|exit_code| match exit_code.as_str() {
-                "10" => Some(SolverReturnCode::Sat),
-                "20" => Some(SolverReturnCode::Unsat),
-                _ => Some(SolverReturnCode::Timeout),
-            })
-        });
-        let wall_time = WALL_TIME.captures(&line).and_then(|c| {
-            c.get(1)
-                .and_then(|wall_time| Some(wall_time.as_str().parse::<f64>().unwrap()))
-        });
-        if exit_code.is_some() {
-            result = exit_code.unwrap()
+/// Builds the result trees of all splits from the results of single
+/// jobs, see extract_results_from_files. Keeps the probe results, so
+/// it can be used repeatedly while jobs are running.
+pub struct ResultCollector<'a> {
+    formula: &'a Formula,
+    options: &'a SplitOptions,
+    // Probe results of each generation.
+    probes: Vec<Vec<ProbeResult>>,
+}
+
+impl<'a> ResultCollector<'a> {
+    pub fn new(formula: &'a Formula, options: &'a SplitOptions) -> ResultCollector<'a> {
+        ResultCollector {
+            formula,
+            options,
+            probes: vec![],
         }
-        if wall_time.is_some() {
-            wall_seconds = wall_time.unwrap()
+    }
+
+    fn probes(&mut self, generation: u32) -> &[ProbeResult] {
+        while self.probes.len() <= generation as usize {
+            let depth = Formula::generation_depth(self.options, self.probes.len() as u32);
+            self.probes.push(if self.options.probe {
+                self.formula.probe_splits(depth)
+            } else {
+                vec![ProbeResult::Open; self.formula.produce_splits(depth).len()]
+            });
         }
+        &self.probes[generation as usize]
     }
 
-    SolverResult {
-        wall_seconds,
-        result,
-        name: name.to_owned()
+    /// Collects a tree for each split of the first generation. The
+    /// result of a split is looked up by its id, exists tells whether
+    /// a split has a result at all, which decides whether the re-splits
+    /// of a timed out split are used.
+    pub fn collect(
+        &mut self,
+        exists: &dyn Fn(&str) -> bool,
+        result: &dyn Fn(&str) -> SolverResult,
+    ) -> Vec<SplitNode> {
+        (0..self.formula.produce_splits(self.options.depth).len())
+            .map(|n| self.collect_node(0, n, exists, result))
+            .collect()
     }
+
+    fn collect_node(
+        &mut self,
+        generation: u32,
+        index: usize,
+        exists: &dyn Fn(&str) -> bool,
+        result: &dyn Fn(&str) -> SolverResult,
+    ) -> SplitNode {
+        if self.probes(generation)[index] != ProbeResult::Open {
+            // Probing decided the split, it was never written.
+            return SplitNode {
+                generation,
+                index,
+                result: SolverResult {
+                    result: SolverReturnCode::Unsat,
+                    ..SolverResult::missing("")
+                },
+                children: vec![],
+                probed: true,
+            };
+        }
+        let id = self.formula.split_id(self.options, generation, index);
+        let result_ = result(&id);
+
+        let mut children = vec![];
+        if !result_.result.is_decided() && self.formula.can_resplit(self.options, generation) {
+            let width = self.formula.generation_width(self.options, generation + 1);
+            let range = index * width..(index + 1) * width;
+            let probes = self.probes(generation + 1)[range.clone()].to_vec();
+            let resplit = range.clone().zip(probes.iter()).any(|(i, p)| {
+                *p == ProbeResult::Open
+                    && exists(&self.formula.split_id(self.options, generation + 1, i))
+            });
+            // Re-splits decided by probing alone are never written.
+            let decided = probes.iter().all(|p| *p != ProbeResult::Open);
+            if resplit || decided {
+                children = range
+                    .map(|i| self.collect_node(generation + 1, i, exists, result))
+                    .collect();
+            }
+        }
+
+        SplitNode {
+            generation,
+            index,
+            result: result_,
+            children,
+            probed: false,
+        }
+    }
+}
+
+/// Reads the assumptions recorded in the header of a written split,
+/// using the assume_line rule of the grammar.
+pub fn read_assumptions(path: &Path) -> Result<Vec<i32>, String> {
+    let f = File::open(path).map_err(|e| format!("Split {:?} could not be read: {}", path, e))?;
+    let mut assumptions = vec![];
+    for line in BufReader::new(f).lines() {
+        // The grammar expects the line ending.
+        let line = line.map_err(|e| format!("Split {:?} could not be read: {}", path, e))? + "\n";
+        if line.starts_with("p ") {
+            break;
+        }
+        if line.starts_with("c assume") {
+            let parsed = QDIMACSParser::parse(Rule::assume_line, &line)
+                .map_err(|e| format!("Invalid assumptions in split {:?}:\n{}", path, e))?;
+            for l in parsed.flat_map(|l| l.into_inner()) {
+                let lit = l.as_str().parse::<i32>().map_err(|e| {
+                    format!(
+                        "Invalid assumption {} in split {:?}: {}",
+                        l.as_str(),
+                        path,
+                        e
+                    )
+                })?;
+                assumptions.push(lit);
+            }
+        }
+    }
+    Ok(assumptions)
+}
+
+// Split files written next to the logs must have been produced with
+// the same assumptions, otherwise the merged results belong to a
+// different subtree.
+fn check_split_assumptions(
+    formula: &Formula,
+    options: &SplitOptions,
+    orig_file: &Path,
+    cwd: &Path,
+) -> Result<(), String> {
+    let splits = formula.produce_splits(options.depth);
+    let written = (0..splits.len()).find_map(|i| {
+        let mut p = PathBuf::new();
+        p.push(cwd);
+        p.push(i.to_string() + ":" + orig_file.file_name().unwrap().to_str().unwrap());
+        p.exists().then_some((i, p))
+    });
+    if let Some((i, p)) = written {
+        let mut expected: Vec<i32> = [formula.assumptions.clone(), splits[i].clone()].concat();
+        let mut recorded = read_assumptions(&p)?;
+        expected.sort_unstable();
+        recorded.sort_unstable();
+        if expected != recorded {
+            return Err(format!(
+                "Split {:?} was produced under assumptions {:?}, but merging expects {:?}",
+                p, recorded, expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The log of a split among the discovered ones. Logs without a name or
+// an id are shared.
+fn find_log<'a>(logs: &'a [LogFile], name: &str, id: &str) -> Option<&'a Path> {
+    let matches = |l: &LogFile, id: Option<&str>| {
+        l.name.as_deref().is_none_or(|n| n == name) && l.id.as_deref() == id
+    };
+    logs.iter()
+        .find(|l| matches(l, Some(id)))
+        .or_else(|| logs.iter().find(|l| matches(l, None)))
+        .map(|l| l.path.as_path())
 }
 
+/// The ids of the splits of the original file with a log of the given
+/// solver among the discovered logs. A log shared by all splits, i.e.
+/// without an id, only counts for the splits it mentions, e.g. the
+/// runs of a BenchExec result file.
+pub fn logged_splits(logs: &[LogFile], name: &str, orig: &str) -> HashSet<String> {
+    let task = Regex::new(&format!("(\\d+(?:\\.\\d+)*):{}", regex::escape(orig))).unwrap();
+    // Not part of a longer id or file name.
+    let alone = |log: &str, start: usize, end: usize| {
+        !log[..start].ends_with(|c: char| c == '.' || c.is_ascii_digit())
+            && !log[end..].starts_with(|c: char| c.is_alphanumeric() || "._-".contains(c))
+    };
+    let mut ids = HashSet::new();
+    for l in logs
+        .iter()
+        .filter(|l| l.name.as_deref().is_none_or(|n| n == name))
+    {
+        match &l.id {
+            Some(id) => {
+                ids.insert(id.to_owned());
+            }
+            None => {
+                let log =
+                    String::from_utf8_lossy(&fs::read(&l.path).unwrap_or_default()).into_owned();
+                ids.extend(
+                    task.captures_iter(&log)
+                        .filter(|c| alone(&log, c.get(0).unwrap().start(), c.get(0).unwrap().end()))
+                        .map(|c| c[1].to_owned()),
+                );
+            }
+        }
+    }
+    ids
+}
+
+/// Lists some of the given ids, there may be thousands.
+pub fn id_list(ids: &[&str]) -> String {
+    let mut list = ids[0..ids.len().min(10)].join(", ");
+    if ids.len() > 10 {
+        list.push_str(", ...");
+    }
+    list
+}
+
+/// Reads the results of all splits of the original file, including
+/// the re-splits of timed out splits. Returns a tree for each split of
+/// the first generation. The logs are found using the given pattern,
+/// splits without a log are Missing. Logs not belonging to any split,
+/// e.g. of a different depth, are reported. Fails if the written
+/// splits were produced under different assumptions.
 pub fn extract_results_from_files(
     orig_file: &Path,
-    names: &Vec<String>,
-    depth: u32,
+    names: &[String],
+    options: &SplitOptions,
     cwd: &Path,
-) -> (Formula, Vec<SolverResult>) {
-    let formula_str = fs::read_to_string(&orig_file).unwrap();
-    let formula = parse_qdimacs(&formula_str, false).unwrap();
-    let splits = formula.produce_splits(depth);
-    (
-        formula,
-        (0..splits.len())
-            .map(|n| {
-                // Follows the Simsala convention.
-                let min_res = names.iter().map(|name| {
-                    let mut filename: String = String::new();
-                    filename.push_str(&name);
-                    filename.push_str("-");
-                    filename.push_str(&n.to_string());
-                    filename.push_str(":");
-                    filename.push_str(orig_file.file_name().unwrap().to_str().unwrap());
-                    filename.push_str(".log");
-                    let mut p = PathBuf::new();
-                    p.push(cwd);
-                    p.push(filename);
-                    extract_result_from_file(&p.as_path(), name)
-                }).min_by(|l, r| l.wall_seconds.partial_cmp(&r.wall_seconds).unwrap()).unwrap();
-                if names.len() > 1 {
-                    println!("  Best solver for {} is {}", n, min_res.name);
-                }
-                min_res
-            })
-            .collect(),
-    )
+    format: LogFormat,
+    pattern: &LogPattern,
+) -> Result<(Formula, Vec<SplitNode>), String> {
+    let formula_str = fs::read_to_string(orig_file).unwrap();
+    let mut formula = parse_qdimacs(&formula_str, false).unwrap();
+    formula.apply_split_options(options, false);
+    check_split_assumptions(&formula, options, orig_file, cwd)?;
+    let orig_name = orig_file.file_name().unwrap().to_str().unwrap();
+    let logs = pattern.discover(cwd, orig_name);
+    let logged: Vec<HashSet<String>> = names
+        .iter()
+        .map(|name| logged_splits(&logs, name, orig_name))
+        .collect();
+    let read: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    let nodes = ResultCollector::new(&formula, options).collect(
+        &|id| logged.iter().any(|ids| ids.contains(id)),
+        &|id| {
+            read.borrow_mut().insert(id.to_owned());
+            let result = names
+                .iter()
+                .map(|name| {
+                    let task = id.to_owned() + ":" + orig_name;
+                    match find_log(&logs, name, id) {
+                        Some(log) => extract_result_from_file(log, name, &task, format),
+                        None => SolverResult::missing(name),
+                    }
+                })
+                // A quick timeout (e.g. a crash) is no answer.
+                .min_by(|l, r| {
+                    (!l.result.is_decided(), l.wall_seconds)
+                        .partial_cmp(&(!r.result.is_decided(), r.wall_seconds))
+                        .unwrap()
+                })
+                .unwrap();
+            if names.len() > 1 {
+                println!("  Best solver for {} is {}", id, result.name);
+            }
+            result
+        },
+    );
+
+    let read = read.into_inner();
+    let mut read_ids: Vec<&str> = read.iter().map(|id| id.as_str()).collect();
+    read_ids.sort_unstable_by_key(|id| split_id_key(id));
+    for name in names.iter() {
+        let missing: Vec<&str> = read_ids
+            .iter()
+            .copied()
+            .filter(|id| find_log(&logs, name, id).is_none())
+            .collect();
+        if !missing.is_empty() {
+            println!(
+                "Missing logs of {} for {} splits: {}",
+                name,
+                missing.len(),
+                id_list(&missing)
+            );
+        }
+        let mut unexpected: Vec<&str> = logs
+            .iter()
+            .filter(|l| l.name.as_deref().is_none_or(|n| n == name))
+            .filter_map(|l| l.id.as_deref())
+            .filter(|id| !read.contains(*id))
+            .collect();
+        unexpected.sort_unstable_by_key(|id| split_id_key(id));
+        if !unexpected.is_empty() {
+            println!(
+                "Unexpected logs of {} for {} splits not part of the run: {}",
+                name,
+                unexpected.len(),
+                id_list(&unexpected)
+            );
+        }
+    }
+    Ok((formula, nodes))
+}
+
+// Sorts split ids numerically, e.g. "2" before "10" before "10.1".
+fn split_id_key(id: &str) -> Vec<usize> {
+    id.split('.').map(|i| i.parse().unwrap_or(0)).collect()
 }
 
 fn to_u64(slice: &[i32]) -> u64 {
@@ -135,9 +599,7 @@ impl IntegerSplitConstraint {
             IntegerSplitKind::LessThan => num < self.target[0][0] as u64,
             IntegerSplitKind::GreaterThan => num > self.target[0][0] as u64,
             IntegerSplitKind::Equals => self.target.iter().any(|tgt| {
-                std::iter::zip(bits, tgt)
-                    .map(|(v, b)| (*b == 1 && *v > 0) || (*b == 0 && *v < 0))
-                    .all(|x| x)
+                std::iter::zip(bits, tgt).all(|(v, b)| (*b == 1 && *v > 0) || (*b == 0 && *v < 0))
             }),
         }
     }
@@ -147,9 +609,25 @@ impl IntegerSplitConstraint {
 pub struct IntegerSplit {
     pub vars: Vec<i32>,
     pub constraints: Vec<IntegerSplitConstraint>,
+    /// Expand the (universal) variables in place instead of producing
+    /// separate splits for them.
+    pub expand: bool,
 }
 
 impl IntegerSplit {
+    /// The default split, splitting a single variable into both of its
+    /// values.
+    pub fn single_var(var: i32) -> IntegerSplit {
+        IntegerSplit {
+            vars: vec![var],
+            constraints: vec![IntegerSplitConstraint {
+                kind: IntegerSplitKind::LessThan,
+                target: vec![vec![2]],
+            }],
+            expand: false,
+        }
+    }
+
     pub fn satisfied_with_num(&self, v: &[i32], num: u64) -> bool {
         self.constraints.iter().any(|x| x.satisfied(v, num))
     }
@@ -176,119 +654,203 @@ impl IntegerSplit {
         unaccounted_expansions as f32 / accounted_expansions as f32
     }
 
+    /// All assignments of the split variables that satisfy the
+    /// constraints, in the order in which splits are produced.
+    pub fn assignments(&self) -> Vec<Vec<i32>> {
+        all_assignments(&self.vars)
+            .into_iter()
+            .filter(|a| self.satisfied(a))
+            .collect()
+    }
+
+    /// Number of produced splits. An expanded split is solved in a
+    /// single job, so it only produces one.
     pub fn nr_of_splits(&self) -> usize {
-        // Just generate all numbers from 0 to 2^n and check all of
-        // them, afterwards count how many passed.
-        let base: u64 = 2;
-        let n: u64 = base.pow(self.vars.len() as u32);
-        (0..n)
-            .map(|i| {
-                let v: Vec<i32> = n
-                    .view_bits::<Lsb0>()
-                    .iter()
-                    .map(|b| if *b { 1 as i32 } else { 0 as i32 })
-                    .collect();
-                self.satisfied_with_num(&v, i)
-            })
-            .filter(|x| *x)
-            .count()
+        if self.expand {
+            1
+        } else {
+            self.assignments().len()
+        }
     }
 }
 
+/// All assignments of the given variables, the first variable being
+/// the most significant bit.
+fn all_assignments(vars: &[i32]) -> Vec<Vec<i32>> {
+    let len = vars.len();
+    let base: u64 = 2;
+    (0..base.pow(len as u32))
+        .map(|i| {
+            vars.iter()
+                .enumerate()
+                .map(|(j, v)| {
+                    if (i >> (len - 1 - j)) & 1 == 1 {
+                        *v
+                    } else {
+                        -*v
+                    }
+                })
+                .collect::<Vec<i32>>()
+        })
+        .collect()
+}
+
+/// Split on a clause (l1 v l2 v ... v lk) of existential literals. It
+/// produces the disjoint branches l1; -l1 & l2; ...; -l1 & ... & lk.
 #[derive(Debug, Clone)]
-pub struct Formula {
-    pub splits: Vec<IntegerSplit>,
-    pub prefix: Vec<i32>,
-    pub matrix: Vec<Vec<i32>>,
-    pub nr_of_variables: i32,
-    pub nr_of_clauses: i32,
+pub struct ClauseSplit {
+    pub lits: Vec<i32>,
+    pub vars: Vec<i32>,
+    /// The clause is implied by the matrix. Otherwise, the branch
+    /// falsifying the whole clause is produced too.
+    pub implied: bool,
+}
+
+impl ClauseSplit {
+    pub fn new(lits: Vec<i32>, matrix: &[Vec<i32>]) -> ClauseSplit {
+        let implied = matrix
+            .iter()
+            .any(|c| !c.is_empty() && c.iter().all(|l| lits.contains(l)));
+        ClauseSplit {
+            vars: lits.iter().map(|l| l.abs()).collect(),
+            lits,
+            implied,
+        }
+    }
+
+    pub fn assignments(&self) -> Vec<Vec<i32>> {
+        let mut assignments: Vec<Vec<i32>> = (0..self.lits.len())
+            .map(|i| {
+                let mut a: Vec<i32> = self.lits[0..i].iter().map(|l| -l).collect();
+                a.push(self.lits[i]);
+                a
+            })
+            .collect();
+        if !self.implied {
+            assignments.push(self.lits.iter().map(|l| -l).collect());
+        }
+        assignments
+    }
+
+    pub fn efficiency(&self) -> f32 {
+        let base: usize = 2;
+        let total: usize = base.pow(self.vars.len() as u32);
+        let branches = self.assignments().len();
+        (total - branches) as f32 / branches as f32
+    }
 }
 
 #[derive(Debug, Clone)]
-pub struct FormulaVars<'a> {
-    formula: &'a Formula,
-    splits_depth: u64,
-    current_idx: u64,
-    max_idx: u64,
+pub enum Split {
+    Int(IntegerSplit),
+    Clause(ClauseSplit),
+    Xor(XorSplit),
 }
 
-impl<'a> Iterator for FormulaVars<'a> {
-    type Item = Vec<i32>;
+impl Split {
+    pub fn vars(&self) -> &[i32] {
+        match self {
+            Split::Int(s) => &s.vars,
+            Split::Clause(s) => &s.vars,
+            Split::Xor(s) => &s.vars,
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.current_idx + 1 > self.max_idx {
-            None
-        } else {
-            let idx = self.current_idx;
-            self.current_idx += 1;
-            let bv = idx.view_bits::<Lsb0>();
-            let vars: Vec<i32> = self.formula.splits[0..self.splits_depth as usize]
-                .iter()
-                .map(|x| x.vars.clone().into_iter())
-                .flatten()
-                .collect();
-            let current = std::iter::zip(bv, vars.into_iter().rev())
-                .map(|(b, v)| if *b { v } else { (v as i32) * -1 as i32 })
-                .rev()
-                .collect();
-            Some(current)
+    /// The assumptions of each branch of this split, in the order in
+    /// which splits are produced.
+    pub fn assignments(&self) -> Vec<Vec<i32>> {
+        match self {
+            Split::Int(s) => s.assignments(),
+            Split::Clause(s) => s.assignments(),
+            Split::Xor(s) => s.assignments(),
+        }
+    }
+
+    pub fn nr_of_splits(&self) -> usize {
+        match self {
+            Split::Int(s) => s.nr_of_splits(),
+            Split::Clause(s) => s.assignments().len(),
+            Split::Xor(s) => s.assignments().len(),
+        }
+    }
+
+    pub fn efficiency(&self) -> f32 {
+        match self {
+            Split::Int(s) => s.efficiency(),
+            Split::Clause(s) => s.efficiency(),
+            // Every parity assignment is a branch, nothing is pruned.
+            Split::Xor(_) => 0.0,
         }
     }
+
+    pub fn is_expanded(&self) -> bool {
+        matches!(self, Split::Int(s) if s.expand)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Formula {
+    pub splits: Vec<Split>,
+    /// Fixed literals the formula is split under, recorded in the
+    /// "c assume" header line.
+    pub assumptions: Vec<i32>,
+    pub prefix: Vec<i32>,
+    pub matrix: Vec<Vec<i32>>,
+    pub nr_of_variables: i32,
+    pub nr_of_clauses: i32,
 }
 
 impl Formula {
     pub fn embedded_splits_max_depth(&self) -> usize {
-        self.splits.iter().map(|x| x.vars.len()).sum()
+        self.splits.iter().map(|x| x.vars().len()).sum()
     }
     pub fn embedded_splits_round_fitting(&self, mut d: i64) -> (u64, u64) {
         let mut computed_depth: u64 = 0;
         let mut split_count = 0;
         for s in self.splits.iter() {
-            d -= s.vars.len() as i64;
+            d -= s.vars().len() as i64;
             if d >= 0 {
-                computed_depth += s.vars.len() as u64;
+                computed_depth += s.vars().len() as u64;
                 split_count += 1;
             } else {
                 return (computed_depth, split_count);
             }
+            if s.is_expanded()
+                && !self
+                    .splits
+                    .get(split_count as usize)
+                    .is_some_and(|n| n.is_expanded())
+            {
+                // Expansion duplicates the inner variables, so
+                // splitting cannot continue below the expanded block.
+                return (computed_depth, split_count);
+            }
         }
         (computed_depth, split_count)
     }
-    fn iterate_possible_vars(&self, bit_depth: u64, splits_depth: u64) -> FormulaVars {
-        let base: u64 = 2;
-        FormulaVars {
-            formula: &self,
-            splits_depth,
-            current_idx: 0,
-            max_idx: base.pow(bit_depth as u32),
-        }
-    }
-    fn produce_splits_from_embedded(&self, bit_depth: u64, splits_depth: u64) -> Vec<Vec<i32>> {
-        assert!(self.splits.len() > 0);
-        let split_var_lengths: Vec<usize> = self.splits[0..splits_depth as usize]
-            .iter()
-            .map(|split| split.vars.len())
-            .collect();
-        let split_ranges: Vec<(usize, usize)> = (0..splits_depth as usize)
-            .map(|i| {
-                let p = split_var_lengths[0..i].iter().sum();
-                (p, p + split_var_lengths[i])
-            })
-            .collect();
-        let splits = &self.splits[0..splits_depth as usize];
-
-        let vars = self
-            .iterate_possible_vars(bit_depth, splits_depth)
-            .filter(|x| {
-                std::iter::zip(splits, &split_ranges)
-                    .all(|(split, (begin, end))| split.satisfied(&x[*begin..*end]))
-            })
-            .collect();
-        vars
+    /// The embedded splits of the given depth, each given as the
+    /// assumptions of all of its split layers.
+    fn produce_split_layers(&self, depth: u32) -> Vec<Vec<Vec<i32>>> {
+        // Expanded splits are not part of the assumptions.
+        let splits = self.used_splits(depth).iter().filter(|s| !s.is_expanded());
+        // The first split varies the slowest.
+        splits.fold(vec![vec![]], |cubes: Vec<Vec<Vec<i32>>>, split| {
+            let assignments = split.assignments();
+            cubes
+                .iter()
+                .flat_map(|c| {
+                    assignments.iter().map(move |a| {
+                        let mut layers = c.clone();
+                        layers.push(a.clone());
+                        layers
+                    })
+                })
+                .collect()
+        })
     }
     fn produce_splits_from_prefix_expansion(&self, bit_depth: u64) -> Vec<Vec<i32>> {
         let base: u64 = 2;
-        let depth: u64 = std::cmp::min(bit_depth as u64, self.prefix.len() as u64);
+        let depth: u64 = std::cmp::min(bit_depth, self.prefix.len() as u64);
 
         (0..(base.pow(depth as u32)))
             .map(|v| {
@@ -308,14 +870,423 @@ impl Formula {
             .collect()
     }
     pub fn produce_splits(&self, depth: u32) -> Vec<Vec<i32>> {
-        if self.splits.len() > 0 {
-            let (rounded_depth, split_count) = self.embedded_splits_round_fitting(depth as i64);
-            self.produce_splits_from_embedded(rounded_depth, split_count)
+        if !self.splits.is_empty() {
+            self.produce_split_layers(depth)
+                .into_iter()
+                .map(|layers| layers.concat())
+                .collect()
         } else {
             let depth: u64 = std::cmp::min(depth as u64, self.prefix.len() as u64);
             self.produce_splits_from_prefix_expansion(depth)
         }
     }
+    /// The embedded splits used for the given depth, outermost first.
+    pub fn used_splits(&self, depth: u32) -> &[Split] {
+        let (_rounded_depth, split_count) = self.embedded_splits_round_fitting(depth as i64);
+        &self.splits[0..split_count as usize]
+    }
+    /// The expanded splits at the end of the used splits.
+    pub fn expanded_splits(&self, depth: u32) -> &[Split] {
+        let used = self.used_splits(depth);
+        let n = used.iter().rev().take_while(|s| s.is_expanded()).count();
+        &used[used.len() - n..]
+    }
+    pub fn is_universal(&self, var: i32) -> bool {
+        self.prefix.contains(&var.abs())
+    }
+    /// Probes every split of the given depth using unit propagation,
+    /// layer by layer. A refuted split is Unsat. If the refuted
+    /// assignment was made on a universal layer, the universal player
+    /// wins the parent and all other splits below it are irrelevant.
+    pub fn probe_splits(&self, depth: u32) -> Vec<ProbeResult> {
+        let cubes = self.produce_split_layers(depth);
+        let universal: Vec<bool> = self
+            .used_splits(depth)
+            .iter()
+            .filter(|s| !s.is_expanded())
+            .map(|s| self.is_universal(s.vars()[0]))
+            .collect();
+
+        let propagator = Propagator::new(self);
+        let mut refuted: HashMap<Vec<i32>, bool> = HashMap::new();
+        let mut decided_parents: HashSet<Vec<i32>> = HashSet::new();
+        let mut results: Vec<ProbeResult> = vec![ProbeResult::Open; cubes.len()];
+
+        for (cube, result) in std::iter::zip(&cubes, results.iter_mut()) {
+            for k in 0..cube.len() {
+                let assumptions = cube[0..=k].concat();
+                let is_refuted = *refuted
+                    .entry(assumptions.clone())
+                    .or_insert_with(|| propagator.propagate(&assumptions).is_none());
+                if is_refuted {
+                    *result = ProbeResult::Refuted;
+                    if universal[k] {
+                        decided_parents.insert(cube[0..k].concat());
+                    }
+                    break;
+                }
+            }
+        }
+
+        for (cube, result) in std::iter::zip(&cubes, results.iter_mut()) {
+            if *result == ProbeResult::Open
+                && (0..cube.len()).any(|k| decided_parents.contains(&cube[0..k].concat()))
+            {
+                *result = ProbeResult::Irrelevant;
+            }
+        }
+        results
+    }
+    /// Applies the options that change the splits of the formula.
+    pub fn apply_split_options(&mut self, options: &SplitOptions, verbose: bool) {
+        // The blocks to expand are numbered in the original prefix, as
+        // fixing universal variables merges blocks.
+        let expanded = self.universal_block_vars(&options.expand);
+        self.assume(&options.under);
+        self.apply_split_heuristic(options.heuristic, verbose);
+        for lits in options.clause_splits.iter() {
+            self.add_clause_split(lits.clone());
+        }
+        if options.xor > 0 {
+            self.add_xor_split(options.xor, &options.xor_vars, options.xor_seed);
+        }
+        self.mark_expanded_vars(&expanded);
+    }
+    /// Fixes the given literals using unit clauses, so splitting
+    /// continues below them. Fixed universal variables become
+    /// existential, like the variables of a split. Splits covering
+    /// fixed variables are dropped.
+    pub fn assume(&mut self, lits: &[i32]) {
+        for l in lits.iter() {
+            if self.assumptions.contains(&-l) {
+                panic!("Contradicting assumptions on variable {}!", l.abs());
+            }
+            if self.assumptions.contains(l) {
+                continue;
+            }
+            if let Some(q) = self.prefix.iter_mut().find(|q| q.abs() == l.abs()) {
+                if *q > 0 {
+                    *q = -*q;
+                }
+            }
+            // Written split files already contain the unit clauses.
+            if !self.matrix.iter().any(|c| c.len() == 1 && c[0] == *l) {
+                self.matrix.push(vec![*l]);
+                self.nr_of_clauses += 1;
+            }
+            self.assumptions.push(*l);
+        }
+        let fixed: HashSet<i32> = self.assumptions.iter().map(|l| l.abs()).collect();
+        self.splits
+            .retain(|s| !s.vars().iter().any(|v| fixed.contains(v)));
+    }
+    /// Adds a clause split in front of the other splits of its
+    /// quantifier block. Single variable splits on its variables are
+    /// dropped, as the clause split already covers them.
+    pub fn add_clause_split(&mut self, lits: Vec<i32>) {
+        let split = Split::Clause(ClauseSplit::new(lits, &self.matrix));
+        check_clause_split_existential(&self.prefix, &split);
+        let vars = split.vars().to_vec();
+        let block = self.quantifier_block(vars[0]);
+        if vars.iter().any(|v| self.quantifier_block(*v) != block) {
+            panic!("Clause split {:?} covers multiple quantifier blocks!", vars);
+        }
+
+        self.splits.retain(
+            |s| !matches!(s, Split::Int(i) if i.vars.len() == 1 && vars.contains(&i.vars[0])),
+        );
+        if let Some(s) = self
+            .splits
+            .iter()
+            .find(|s| s.vars().iter().any(|v| vars.contains(v)))
+        {
+            panic!("Clause split {:?} overlaps with split {:?}!", vars, s);
+        }
+
+        let pos = self
+            .splits
+            .iter()
+            .position(|s| self.quantifier_block(s.vars()[0]) >= block)
+            .unwrap_or(self.splits.len());
+        self.splits.insert(pos, split);
+    }
+    /// The variables of the given universal quantifier blocks.
+    pub fn universal_block_vars(&self, blocks: &[usize]) -> Vec<i32> {
+        for b in blocks.iter() {
+            let universal = self
+                .prefix
+                .iter()
+                .any(|q| *q > 0 && self.quantifier_block(*q) == *b);
+            if !universal {
+                panic!(
+                    "Only universal quantifier blocks can be expanded, {} is not!",
+                    b
+                );
+            }
+        }
+        self.prefix
+            .iter()
+            .copied()
+            .filter(|q| blocks.contains(&self.quantifier_block(*q)))
+            .collect()
+    }
+    /// Marks the splits on the given universal variables to be expanded
+    /// instead of split.
+    pub fn mark_expanded_vars(&mut self, vars: &[i32]) {
+        for s in self.splits.iter_mut() {
+            if let Split::Int(s) = s {
+                s.expand = vars.contains(&s.vars[0]);
+            }
+        }
+    }
+    /// The formula of a single split at the given depth, i.e. the
+    /// formula under the assumptions of the split, with the expanded
+    /// splits expanded.
+    pub fn split_formula(&self, depth: u32, split: &[i32]) -> Formula {
+        let mut assumed_f: Formula = Clone::clone(self);
+        // Flips forall quantifiers to existential, as there is a
+        // specific assignment. The split is recorded together with the
+        // fixed assumptions.
+        assumed_f.assume(split);
+        let expanded = self.expanded_splits(depth);
+        if !expanded.is_empty() {
+            let vars: Vec<i32> = expanded.iter().flat_map(|s| s.vars().to_vec()).collect();
+            // All combinations of the assignments of the expanded splits.
+            let assignments = expanded.iter().fold(vec![vec![]], |acc, s| {
+                acc.iter()
+                    .flat_map(|a: &Vec<i32>| {
+                        s.assignments()
+                            .into_iter()
+                            .map(move |b| [a.clone(), b].concat())
+                    })
+                    .collect()
+            });
+            assumed_f = assumed_f.expand_universals(&vars, &assignments);
+        }
+        assumed_f
+    }
+    /// Whether the outermost assignment printed by a solver certifies
+    /// the given result. In QDO, this is the case if the outermost
+    /// block belongs to the winner, i.e. it is existential for Sat and
+    /// universal for Unsat. Otherwise, the printed assignment is
+    /// arbitrary.
+    pub fn certifies(&self, result: SolverReturnCode) -> bool {
+        let universal = self.prefix.first().is_some_and(|q| *q > 0);
+        match result {
+            SolverReturnCode::Sat => !universal,
+            SolverReturnCode::Unsat => universal,
+            _ => false,
+        }
+    }
+    /// Checks the certificate a solver printed for the split with the
+    /// given cube, see certifies, i.e. the existential assignment of a
+    /// Sat split or the universal counter-assignment of an Unsat one.
+    /// It has to agree with the cube and the fixed assumptions, which
+    /// matters if the outermost block is split. A model of a Sat split
+    /// without universal variables has to satisfy the matrix too.
+    pub fn check_certificate(
+        &self,
+        cube: &[i32],
+        result: SolverReturnCode,
+        certificate: &[i32],
+    ) -> Result<(), String> {
+        let assigned: HashSet<i32> = certificate.iter().copied().collect();
+        if let Some(l) = certificate.iter().find(|l| assigned.contains(&-**l)) {
+            return Err(format!("variable {} is assigned both ways", l.abs()));
+        }
+        if let Some(l) = self
+            .assumptions
+            .iter()
+            .chain(cube)
+            .find(|l| assigned.contains(&-**l))
+        {
+            return Err(format!("{} contradicts the assumption {}", -l, l));
+        }
+        if result == SolverReturnCode::Sat && !self.prefix.iter().any(|q| *q > 0) {
+            let model: HashSet<i32> = assigned.into_iter().chain(cube.iter().copied()).collect();
+            if let Some(c) = self
+                .matrix
+                .iter()
+                .find(|c| !c.iter().any(|l| model.contains(l)))
+            {
+                return Err(format!("the model does not satisfy the clause {:?}", c));
+            }
+        }
+        Ok(())
+    }
+    /// Expands the given universal variables, which all have to be
+    /// from the same quantifier block. Produces the conjunction of the
+    /// cofactors for each of the given assignments, where variables
+    /// quantified after the block get fresh copies for each cofactor.
+    pub fn expand_universals(&self, vars: &[i32], assignments: &[Vec<i32>]) -> Formula {
+        let block = self.quantifier_block(vars[0]);
+        let inner: HashSet<i32> = self
+            .prefix
+            .iter()
+            .map(|q| q.abs())
+            .filter(|v| self.quantifier_block(*v) > block)
+            .collect();
+        let is_inner = |v: i32| inner.contains(&v.abs());
+        let mut next_var = self.nr_of_variables + 1;
+        let renames: Vec<HashMap<i32, i32>> = (0..assignments.len())
+            .map(|c| {
+                self.prefix
+                    .iter()
+                    .map(|q| q.abs())
+                    .filter(|v| is_inner(*v))
+                    .map(|v| {
+                        if c == 0 {
+                            (v, v)
+                        } else {
+                            next_var += 1;
+                            (v, next_var - 1)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let rename = |c: usize, l: i32| -> i32 {
+            match renames[c].get(&l.abs()) {
+                Some(v) => *v * sign(l),
+                None => l,
+            }
+        };
+
+        // Blocks after the expanded one are merged over all copies.
+        let mut prefix: Vec<i32> = vec![];
+        for q in self.prefix.iter() {
+            if !is_inner(*q) && !vars.contains(&q.abs()) {
+                prefix.push(*q);
+            }
+        }
+        let mut inner_block = block + 1;
+        while self
+            .prefix
+            .iter()
+            .any(|q| self.quantifier_block(*q) == inner_block)
+        {
+            for c in 0..assignments.len() {
+                for q in self.prefix.iter() {
+                    if self.quantifier_block(*q) == inner_block {
+                        prefix.push(rename(c, *q));
+                    }
+                }
+            }
+            inner_block += 1;
+        }
+
+        let mut matrix: Vec<Vec<i32>> = vec![];
+        for clause in self.matrix.iter() {
+            let affected = clause
+                .iter()
+                .any(|l| vars.contains(&l.abs()) || is_inner(*l));
+            if !affected {
+                matrix.push(clause.clone());
+                continue;
+            }
+            for (c, a) in assignments.iter().enumerate() {
+                if clause.iter().any(|l| a.contains(l)) {
+                    continue;
+                }
+                matrix.push(
+                    clause
+                        .iter()
+                        .filter(|l| !a.contains(&-**l))
+                        .map(|l| rename(c, *l))
+                        .collect(),
+                );
+            }
+        }
+
+        Formula {
+            splits: vec![],
+            assumptions: self.assumptions.clone(),
+            prefix,
+            nr_of_variables: next_var - 1,
+            nr_of_clauses: matrix.len() as i32,
+            matrix,
+        }
+    }
+    /// Index of the quantifier block the variable is bound in. Free
+    /// variables and all variables of DIMACS formulas are in block 0.
+    pub fn quantifier_block(&self, var: i32) -> usize {
+        let mut block = 0;
+        let mut last_q = 0;
+        for q in self.prefix.iter() {
+            if last_q == 0 || sign(last_q) != sign(*q) {
+                block += 1;
+            }
+            if q.abs() == var.abs() {
+                return block;
+            }
+            last_q = *q;
+        }
+        0
+    }
+    /// Selects and orders split variables using the given heuristic.
+    /// Plain DIMACS formulas without embedded splits get their split
+    /// variables ranked over the whole matrix. Otherwise, splits are
+    /// reordered inside of their quantifier block, as blocks themselves
+    /// cannot be reordered.
+    pub fn apply_split_heuristic(&mut self, heuristic: SplitHeuristic, verbose: bool) {
+        if heuristic == SplitHeuristic::Prefix {
+            return;
+        }
+        if self.prefix.is_empty() && self.splits.is_empty() {
+            self.select_split_vars(heuristic, verbose);
+        } else {
+            self.order_splits_in_blocks(heuristic, verbose);
+        }
+    }
+    fn select_split_vars(&mut self, heuristic: SplitHeuristic, verbose: bool) {
+        let mut vars: Vec<i32> = self.matrix.iter().flatten().map(|l| l.abs()).collect();
+        vars.sort_unstable();
+        vars.dedup();
+        vars.retain(|v| !self.assumptions.iter().any(|l| l.abs() == *v));
+        let ordered = heuristics::order_variables(self, heuristic, &vars);
+        let n = std::cmp::min(ordered.len(), 64);
+        if verbose {
+            println!(
+                "Split variables selected by {}: {:?}",
+                heuristic,
+                &ordered[0..n]
+            );
+        }
+        self.splits = ordered[0..n]
+            .iter()
+            .map(|v| Split::Int(IntegerSplit::single_var(*v)))
+            .collect();
+    }
+    fn order_splits_in_blocks(&mut self, heuristic: SplitHeuristic, verbose: bool) {
+        let vars: Vec<i32> = self.splits.iter().flat_map(|s| s.vars().to_vec()).collect();
+        let var_scores = heuristics::score_variables(self, heuristic, &vars);
+        let mut var_scores = var_scores.into_iter();
+        // A split is scored by the sum of its variables.
+        let mut scored: Vec<(Split, f64)> = self
+            .splits
+            .drain(..)
+            .map(|s| {
+                let score = var_scores.by_ref().take(s.vars().len()).sum();
+                (s, score)
+            })
+            .collect();
+
+        let mut begin = 0;
+        while begin < scored.len() {
+            let block = self.quantifier_block(scored[begin].0.vars()[0]);
+            let len = scored[begin..]
+                .iter()
+                .take_while(|(s, _)| self.quantifier_block(s.vars()[0]) == block)
+                .count();
+            scored[begin..begin + len].sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            begin += len;
+        }
+
+        self.splits = scored.into_iter().map(|(s, _)| s).collect();
+        if verbose {
+            println!("Splits reordered by {}: {:?}", heuristic, self.splits);
+        }
+    }
 }
 
 fn sign(n: i32) -> i32 {
@@ -330,9 +1301,20 @@ fn sign(n: i32) -> i32 {
 
 pub fn write_qdimacs(tgt: &Path, formula: &Formula) -> std::io::Result<()> {
     let mut file = BufWriter::new(File::create(tgt).expect("File could not be created!"));
-    write!(
+    write_qdimacs_to(&mut file, formula)?;
+    file.flush()
+}
+
+/// Writes the formula in QDIMACS to any writer, e.g. the stdin of a
+/// solver.
+pub fn write_qdimacs_to<W: Write>(file: &mut W, formula: &Formula) -> std::io::Result<()> {
+    if !formula.assumptions.is_empty() {
+        let lits: Vec<String> = formula.assumptions.iter().map(|l| l.to_string()).collect();
+        writeln!(file, "c assume {} 0", lits.join(" "))?;
+    }
+    writeln!(
         file,
-        "p cnf {} {}\n",
+        "p cnf {} {}",
         formula.nr_of_variables, formula.nr_of_clauses
     )?;
 
@@ -341,12 +1323,12 @@ pub fn write_qdimacs(tgt: &Path, formula: &Formula) -> std::io::Result<()> {
         let q = *q_;
         if q < 0 && last_q >= 0 {
             if last_q != 0 {
-                write!(file, " 0\n")?;
+                writeln!(file, " 0")?;
             }
             write!(file, "e {}", -q)?;
         } else if q > 0 && last_q <= 0 {
             if last_q != 0 {
-                write!(file, " 0\n")?;
+                writeln!(file, " 0")?;
             }
             write!(file, "a {}", q)?;
         } else {
@@ -360,7 +1342,7 @@ pub fn write_qdimacs(tgt: &Path, formula: &Formula) -> std::io::Result<()> {
     }
 
     if last_q != 0 {
-        write!(file, " 0\n")?;
+        writeln!(file, " 0")?;
     }
 
     for clause in formula.matrix.iter() {
@@ -368,10 +1350,10 @@ pub fn write_qdimacs(tgt: &Path, formula: &Formula) -> std::io::Result<()> {
 
         for l in clause.iter() {
             space_separated.push_str(&l.to_string());
-            space_separated.push_str(" ");
+            space_separated.push(' ');
         }
 
-        write!(file, "{}0\n", space_separated)?;
+        writeln!(file, "{}0", space_separated)?;
     }
     Ok(())
 }
@@ -381,16 +1363,23 @@ fn var_constraint_to_nr_of_bits(v: i32) -> i32 {
     vf.log2().ceil() as i32
 }
 
+fn check_clause_split_existential(prefix: &[i32], split: &Split) {
+    if let Split::Clause(c) = split {
+        if let Some(v) = c.vars.iter().find(|v| prefix.contains(v)) {
+            panic!(
+                "Clause splits must only cover existential variables! Variable {} is universal.",
+                v
+            );
+        }
+    }
+}
+
 // Maximize int-split efficiency m^eff (inside of quantifier blocks)
-fn split_eff_cmp(a: &IntegerSplit, b: &IntegerSplit) -> std::cmp::Ordering {
+fn split_eff_cmp(a: &Split, b: &Split) -> std::cmp::Ordering {
     b.efficiency().partial_cmp(&a.efficiency()).unwrap()
 }
 
-fn optimize_prefix_quantifier_block_local(
-    prefix: &Vec<i32>,
-    splits: &mut Vec<IntegerSplit>,
-    verbose: bool,
-) {
+fn optimize_prefix_quantifier_block_local(prefix: &[i32], splits: &mut [Split], verbose: bool) {
     let mut splits_begin: usize = 0;
     let mut i = 0;
     while i < prefix.len() - 1 {
@@ -412,7 +1401,7 @@ fn optimize_prefix_quantifier_block_local(
         let split_count = splits[splits_begin..]
             .iter()
             .take_while(|s| {
-                varcount += s.vars.len();
+                varcount += s.vars().len();
                 varcount <= l
             })
             .count();
@@ -450,10 +1439,17 @@ pub fn parse_qdimacs(qdimacs: &str, verbose: bool) -> Result<Formula, String> {
     let mut nr_of_clauses: i32 = 0;
     let mut prefix: Vec<i32> = vec![];
     let mut matrix: Vec<Vec<i32>> = vec![];
-    let mut splits: Vec<IntegerSplit> = vec![];
+    let mut splits: Vec<Split> = vec![];
+    let mut assumptions: Vec<i32> = vec![];
 
     for line in file.into_inner() {
         match line.as_rule() {
+            Rule::assume_line => {
+                assumptions.extend(
+                    line.into_inner()
+                        .map(|l| l.as_str().parse::<i32>().unwrap()),
+                );
+            }
             Rule::problem_line => {
                 let mut inner_rules = line.into_inner();
                 let nr_of_variables_inner = inner_rules.next().unwrap();
@@ -532,15 +1528,27 @@ pub fn parse_qdimacs(qdimacs: &str, verbose: bool) -> Result<Formula, String> {
                     constraints.push(constraint);
 
                     if !already_have_next {
-                        let next = inner_rules.next();
-                        if next.is_some() {
-                            vars_or_cmp = next.unwrap();
+                        if let Some(next) = inner_rules.next() {
+                            vars_or_cmp = next;
                         } else {
                             break;
                         }
                     }
                 }
-                splits.push(IntegerSplit { vars, constraints });
+                splits.push(Split::Int(IntegerSplit {
+                    vars,
+                    constraints,
+                    expand: false,
+                }));
+            }
+            Rule::clause_split_line => {
+                let lits: Vec<i32> = line
+                    .into_inner()
+                    .map(|l| l.as_str().parse::<i32>().unwrap())
+                    .collect();
+                // Whether the clause is implied is known after the
+                // matrix was parsed.
+                splits.push(Split::Clause(ClauseSplit::new(lits, &[])));
             }
             Rule::quant_set => {
                 let mut inner_rules = line.into_inner();
@@ -548,7 +1556,7 @@ pub fn parse_qdimacs(qdimacs: &str, verbose: bool) -> Result<Formula, String> {
                 let neg = quantifier.eq("e");
                 for var in inner_rules {
                     let var_num = var.as_str().parse::<i32>().unwrap();
-                    let quantified_var = if neg { var_num * -1 } else { var_num };
+                    let quantified_var = if neg { -var_num } else { var_num };
                     prefix.push(quantified_var);
                 }
             }
@@ -567,10 +1575,19 @@ pub fn parse_qdimacs(qdimacs: &str, verbose: bool) -> Result<Formula, String> {
     // Fixup integer splits without assigned variables based on their properties.
     let mut prefix_start = 0;
     for s in splits.iter_mut() {
-        if s.constraints.len() < 1 {
+        let s = match s {
+            Split::Int(s) => s,
+            Split::Clause(s) => {
+                *s = ClauseSplit::new(s.lits.clone(), &matrix);
+                prefix_start += s.vars.len() as i32;
+                continue;
+            }
+            Split::Xor(_) => continue,
+        };
+        if s.constraints.is_empty() {
             panic!("Require some constraints for int splits!");
         }
-        if s.vars.len() == 0 {
+        if s.vars.is_empty() {
             let nr_of_bits = match s.constraints[0].kind {
                 IntegerSplitKind::LessThan | IntegerSplitKind::GreaterThan => {
                     var_constraint_to_nr_of_bits(s.constraints[0].target[0][0])
@@ -587,28 +1604,23 @@ pub fn parse_qdimacs(qdimacs: &str, verbose: bool) -> Result<Formula, String> {
         }
     }
 
-    if splits.len() == 0 && prefix.len() > 0 {
+    if splits.is_empty() && !prefix.is_empty() {
         // Fill integer splits with default splitting, i.e. one
         // variable in order of prefix < 2. Every QBF thus becomes
         // splittable using just this technique!
         let n = std::cmp::min(prefix.len(), 64);
         splits = prefix[0..n]
-            .into_iter()
-            .map(|p| IntegerSplit {
-                vars: vec![p.abs()],
-                constraints: vec![IntegerSplitConstraint {
-                    kind: IntegerSplitKind::LessThan,
-                    target: vec![vec![2]],
-                }],
-            })
+            .iter()
+            .map(|p| Split::Int(IntegerSplit::single_var(p.abs())))
             .collect()
     }
 
     // Consistency Check with Quantifier Blocks
     if !prefix.is_empty() {
         for s in splits.iter() {
+            check_clause_split_existential(&prefix, s);
             let mut last_q = 0;
-            for v in s.vars.iter() {
+            for v in s.vars().iter() {
                 let q_pos = prefix.iter().position(|q| q.abs() == *v).unwrap();
                 let q = prefix[q_pos];
                 if last_q != 0 && sign(last_q) != sign(q) {
@@ -638,13 +1650,16 @@ pub fn parse_qdimacs(qdimacs: &str, verbose: bool) -> Result<Formula, String> {
         optimize_prefix_quantifier_block_local(&prefix, &mut splits, verbose);
     }
 
-    Ok(Formula {
+    let mut formula = Formula {
         splits,
+        assumptions: vec![],
         prefix,
         matrix,
         nr_of_variables,
         nr_of_clauses,
-    })
+    };
+    formula.assume(&assumptions);
+    Ok(formula)
 }
 
 #[cfg(test)]
@@ -658,9 +1673,189 @@ mod tests {
         assert_eq!(var_constraint_to_nr_of_bits(2), 1);
     }
 
+    #[test]
+    fn test_heuristic_respects_blocks() {
+        let mut f = parse_qdimacs(
+            "p cnf 4 3\ne 1 2 0\na 3 4 0\n2 3 0\n2 4 0\n-2 -4 1 0\n",
+            false,
+        )
+        .unwrap();
+        f.apply_split_heuristic(SplitHeuristic::Occurrence, false);
+        let vars: Vec<i32> = f.splits.iter().map(|s| s.vars()[0]).collect();
+        assert_eq!(vars, vec![2, 1, 4, 3]);
+        assert_eq!(f.quantifier_block(4), 2);
+    }
+
+    #[test]
+    fn test_probe_splits() {
+        // Setting 1 and then -2 is refuted. As 2 is universal, the
+        // universal player wins below 1 and the rest is irrelevant.
+        let f = parse_qdimacs(
+            "p cnf 3 2\ne 1 0\na 2 0\ne 3 0\n-1 2 3 0\n-1 2 -3 0\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            f.probe_splits(3),
+            vec![
+                ProbeResult::Open,
+                ProbeResult::Open,
+                ProbeResult::Open,
+                ProbeResult::Open,
+                ProbeResult::Refuted,
+                ProbeResult::Refuted,
+                ProbeResult::Irrelevant,
+                ProbeResult::Irrelevant,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_universals() {
+        let f = parse_qdimacs("p cnf 3 2\ne 1 0\na 2 0\ne 3 0\n1 2 3 0\n-2 -3 0\n", false).unwrap();
+        let e = f.expand_universals(&[2], &[vec![-2], vec![2]]);
+        assert_eq!(e.prefix, vec![-1, -3, -4]);
+        assert_eq!(e.matrix, vec![vec![1, 3], vec![-4]]);
+        assert_eq!(e.nr_of_variables, 4);
+    }
+
+    #[test]
+    fn test_under_expand() {
+        let mut f = parse_qdimacs(
+            "p cnf 5 1\ne 1 0\na 2 0\ne 3 0\na 4 0\ne 5 0\n1 2 3 4 5 0\n",
+            false,
+        )
+        .unwrap();
+        // Fixing 2 merges the first three blocks, 4 is still expanded.
+        let options = SplitOptions {
+            depth: 5,
+            under: vec![2],
+            expand: vec![4],
+            ..Default::default()
+        };
+        f.apply_split_options(&options, false);
+        assert_eq!(f.quantifier_block(4), 2);
+        let expanded: Vec<i32> = f
+            .expanded_splits(5)
+            .iter()
+            .flat_map(|s| s.vars().to_vec())
+            .collect();
+        assert_eq!(expanded, vec![4]);
+    }
+
+    #[test]
+    fn test_xor_depth() {
+        let options = SplitOptions {
+            depth: 1,
+            xor: 2,
+            ..Default::default()
+        };
+        assert!(options.check().is_err());
+        let options = SplitOptions {
+            depth: 2,
+            ..options
+        };
+        assert!(options.check().is_ok());
+    }
+
+    #[test]
+    fn test_assignments() {
+        let f = parse_qdimacs("cs int [ 1 2 ] = { 01 10 }\np cnf 2 1\n1 2 0\n", false).unwrap();
+        assert_eq!(f.splits[0].assignments(), vec![vec![-1, 2], vec![1, -2]]);
+        assert_eq!(f.splits[0].nr_of_splits(), 2);
+    }
+
+    #[test]
+    fn test_clause_split() {
+        let f = parse_qdimacs(
+            "cs clause [ 1 -2 3 ]\np cnf 3 1\ne 1 2 3 0\n1 -2 3 0\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(f.splits.len(), 1);
+        assert_eq!(
+            f.produce_splits(3),
+            vec![vec![1], vec![-1, -2], vec![-1, 2, 3]]
+        );
+
+        // Not implied by the matrix, so the falsifying branch is needed.
+        let mut f = parse_qdimacs("p cnf 3 1\ne 1 2 3 0\n1 2 0\n", false).unwrap();
+        f.add_clause_split(vec![2, 3]);
+        assert_eq!(f.splits.len(), 2);
+        assert_eq!(f.splits[0].nr_of_splits(), 3);
+        assert_eq!(f.produce_splits(3).len(), 6);
+    }
+
+    #[test]
+    fn test_assume_header() {
+        let f = parse_qdimacs(
+            "c assume 2 0\np cnf 3 1\ne 1 0\na 2 0\ne 3 0\n1 2 3 0\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(f.assumptions, vec![2]);
+        assert_eq!(f.prefix, vec![-1, -2, -3]);
+        assert_eq!(
+            f.produce_splits(2),
+            vec![vec![-1, -3], vec![-1, 3], vec![1, -3], vec![1, 3]]
+        );
+        assert_eq!(f.matrix.last(), Some(&vec![2]));
+    }
+
+    #[test]
+    fn test_read_assumptions() {
+        let path = std::env::temp_dir().join(format!("0:assume-{}.cnf", std::process::id()));
+        fs::write(&path, "c assume 2 -3 0\np cnf 3 1\n1 2 3 0\n").unwrap();
+        assert_eq!(read_assumptions(&path), Ok(vec![2, -3]));
+        fs::write(&path, "c assume 2 x 0\np cnf 3 1\n1 2 3 0\n").unwrap();
+        let e = read_assumptions(&path).unwrap_err();
+        assert!(e.contains(path.to_str().unwrap()));
+        fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn test_sign() {
         assert_eq!(sign(2), 1);
         assert_eq!(sign(-2), -1);
     }
+
+    #[test]
+    fn test_classify_result() {
+        use SolverReturnCode::*;
+        assert_eq!(classify_result(Some(10), Some("out of time")), Sat);
+        assert_eq!(classify_result(Some(124), Some("out")), Timeout);
+        assert_eq!(classify_result(Some(1), Some("out of memory")), MemoryOut);
+        assert_eq!(classify_result(None, Some("signal(11)")), Crashed);
+        assert_eq!(classify_result(Some(139), None), Crashed);
+        assert_eq!(classify_result(Some(1), Some("ok")), Error);
+        assert_eq!(classify_result(Some(0), Some("ok")), Unknown);
+        assert_eq!(classify_result(None, None), Unknown);
+        let missing = extract_result_from_file(
+            Path::new("/nonexistent.log"),
+            &"s".to_string(),
+            "0:f.qdimacs",
+            LogFormat::Auto,
+        );
+        assert_eq!(missing.result, Missing);
+    }
+
+    #[test]
+    fn test_check_certificate() {
+        let f = parse_qdimacs("p cnf 3 2\n1 2 0\n-1 3 0\n", false).unwrap();
+        let sat = SolverReturnCode::Sat;
+        assert!(f.certifies(sat));
+        assert!(!f.certifies(SolverReturnCode::Unsat));
+        assert_eq!(f.check_certificate(&[1], sat, &[1, -2, 3]), Ok(()));
+        assert!(f.check_certificate(&[-1], sat, &[1, -2, 3]).is_err());
+        assert!(f.check_certificate(&[1], sat, &[1, 2, -3]).is_err());
+
+        // The universal counter-assignment of an Unsat split has to
+        // agree with a split of its block.
+        let q = parse_qdimacs("p cnf 2 1\na 1 0\ne 2 0\n1 2 0\n", false).unwrap();
+        let unsat = SolverReturnCode::Unsat;
+        assert!(q.certifies(unsat));
+        assert!(!q.certifies(SolverReturnCode::Sat));
+        assert_eq!(q.check_certificate(&[-1], unsat, &[-1]), Ok(()));
+        assert!(q.check_certificate(&[1], unsat, &[-1]).is_err());
+    }
 }
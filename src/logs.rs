@@ -0,0 +1,554 @@
+use std::path::{Path, PathBuf};
+
+use file_matcher::FilesNamed;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{classify_result, JobInfo, SolverReturnCode};
+
+/// Everything known about a job from its log. Unknown values are None.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogInfo {
+    pub exit_code: Option<i32>,
+    /// Status in the terms of runlim, e.g. "ok", "out of time", "out of
+    /// memory" or "signal(11)".
+    pub status: Option<String>,
+    /// Answer printed by the solver itself.
+    pub answer: Option<SolverReturnCode>,
+    pub wall_seconds: Option<f64>,
+    pub cpu_seconds: Option<f64>,
+    pub peak_memory_kb: Option<u64>,
+    pub job: JobInfo,
+    /// Assignment printed by the solver, see certificate.
+    pub certificate: Vec<i32>,
+}
+
+impl LogInfo {
+    /// The result of the job. Without an exit code or status, e.g. for
+    /// plain solver output, the answer of the solver decides. An answer
+    /// contradicting the exit code gives a Mismatch.
+    pub fn result(&self) -> SolverReturnCode {
+        if self.exit_code.is_none() && self.status.is_none() {
+            return self.answer.unwrap_or(SolverReturnCode::Unknown);
+        }
+        let result = classify_result(self.exit_code, self.status.as_deref());
+        match self.answer {
+            Some(answer) if result.is_decided() && answer != result => SolverReturnCode::Mismatch,
+            // Some solvers exit with 0 after answering.
+            Some(answer)
+                if result == SolverReturnCode::Unknown
+                    && matches!(self.status.as_deref(), None | Some("ok")) =>
+            {
+                answer
+            }
+            _ => result,
+        }
+    }
+}
+
+/// A format of logs results can be read from.
+pub trait ResultSource {
+    /// Whether the log looks like it is in this format.
+    fn detect(&self, log: &str) -> bool;
+    /// Reads the log of the given task, i.e. the name of the split file.
+    fn parse(&self, log: &str, task: &str) -> LogInfo;
+}
+
+/// Output of runlim, e.g. written by Simsala's submit.pl or the run
+/// subcommand.
+pub struct Runlim;
+
+/// Output of GNU time with -v.
+pub struct GnuTime;
+
+/// BenchExec result XML. The run of the task is picked, so the result
+/// file of a whole run set works too.
+pub struct BenchExec;
+
+/// Plain solver output with "s cnf 1" / "s cnf 0" or "s SATISFIABLE" /
+/// "s UNSATISFIABLE" lines.
+pub struct SolverOutput;
+
+// Answer lines of QDIMACS and the SAT competition, and the final
+// comment of caqe.
+fn answer(line: &str) -> Option<SolverReturnCode> {
+    lazy_static! {
+        static ref ANSWER: Regex = Regex::new(
+            "^(?:s (?:cnf (-?\\d+)|(SATISFIABLE|UNSATISFIABLE|UNKNOWN))|c (Satisfiable|Unsatisfiable)$)"
+        )
+        .unwrap();
+    }
+    let c = ANSWER.captures(line)?;
+    let answer = c.get(1).or(c.get(2)).or(c.get(3)).unwrap().as_str();
+    Some(match answer {
+        "1" | "SATISFIABLE" | "Satisfiable" => SolverReturnCode::Sat,
+        "0" | "UNSATISFIABLE" | "Unsatisfiable" => SolverReturnCode::Unsat,
+        _ => SolverReturnCode::Unknown,
+    })
+}
+
+// The literals of QDO "V" lines (one literal each) and of model "v"
+// lines, terminated by 0.
+fn certificate(log: &str) -> Vec<i32> {
+    log.lines()
+        .filter_map(|l| l.strip_prefix("V ").or_else(|| l.strip_prefix("v ")))
+        .flat_map(|l| {
+            l.split_whitespace()
+                .map_while(|lit| lit.parse::<i32>().ok())
+                .take_while(|lit| *lit != 0)
+        })
+        .collect()
+}
+
+impl ResultSource for Runlim {
+    fn detect(&self, log: &str) -> bool {
+        log.lines().any(|l| l.starts_with("[runlim]"))
+    }
+
+    fn parse(&self, log: &str, _task: &str) -> LogInfo {
+        lazy_static! {
+            static ref EXIT_CODE: Regex =
+                Regex::new("Command exited with non-zero status (\\d+)").unwrap();
+            static ref RESULT: Regex = Regex::new("^\\[runlim\\] result:\\s*(\\d+)").unwrap();
+            static ref WALL_TIME: Regex =
+                Regex::new("^\\[runlim\\] real:\\s*(\\d+(?:\\.\\d+))").unwrap();
+            static ref STATUS: Regex = Regex::new("^\\[runlim\\] status:\\s*(.*?)\\s*$").unwrap();
+            static ref CPU_TIME: Regex =
+                Regex::new("^\\[runlim\\] time:\\s*(\\d+(?:\\.\\d+)?)").unwrap();
+            static ref SPACE: Regex =
+                Regex::new("^\\[runlim\\] space:\\s*(\\d+(?:\\.\\d+)?)\\s*(KB|MB)").unwrap();
+            static ref FIELD: Regex = Regex::new(
+                "^(?:\\[runlim\\]|c submit\\.pl:|c qdimacs_splitter run:) ([a-z ]+):\\s*(.*?)\\s*$"
+            )
+            .unwrap();
+        }
+        let mut info = LogInfo::default();
+        for line in log.lines() {
+            if let Some(c) = CPU_TIME.captures(line) {
+                info.cpu_seconds = c[1].parse().ok();
+            }
+            if let Some(c) = SPACE.captures(line) {
+                let factor = if &c[2] == "MB" { 1024.0 } else { 1.0 };
+                info.peak_memory_kb = c[1].parse::<f64>().ok().map(|s| (s * factor) as u64);
+            }
+            if let Some(c) = FIELD.captures(line) {
+                let value = Some(c[2].to_owned());
+                match &c[1] {
+                    "host" => info.job.host = value,
+                    "load" => info.job.load = c[2].parse().ok(),
+                    "name" => info.job.run_name = value,
+                    "task" => info.job.task = value,
+                    "commit" => info.job.commit = value,
+                    "additional args" | "args" => info.job.args = value,
+                    _ => {}
+                }
+            }
+            if let Some(c) = EXIT_CODE.captures(line).or_else(|| RESULT.captures(line)) {
+                info.exit_code = c[1].parse().ok();
+            }
+            if let Some(c) = WALL_TIME.captures(line) {
+                info.wall_seconds = c[1].parse().ok();
+            }
+            if let Some(c) = STATUS.captures(line) {
+                info.status = Some(c[1].to_owned());
+            }
+            info.answer = answer(line).or(info.answer);
+        }
+        info
+    }
+}
+
+// Parses "h:mm:ss" or "m:ss.ss".
+fn parse_clock(clock: &str) -> Option<f64> {
+    clock.split(':').try_fold(0.0, |acc, part| {
+        Some(acc * 60.0 + part.parse::<f64>().ok()?)
+    })
+}
+
+impl ResultSource for GnuTime {
+    fn detect(&self, log: &str) -> bool {
+        log.contains("Command being timed:")
+    }
+
+    fn parse(&self, log: &str, _task: &str) -> LogInfo {
+        let mut info = LogInfo::default();
+        let mut cpu: Option<f64> = None;
+        for line in log.lines() {
+            info.answer = answer(line).or(info.answer);
+            let Some((key, value)) = line.trim().split_once(": ") else {
+                continue;
+            };
+            match key {
+                "User time (seconds)" | "System time (seconds)" => {
+                    cpu = Some(cpu.unwrap_or(0.0) + value.parse::<f64>().unwrap_or(0.0));
+                }
+                "Elapsed (wall clock) time (h:mm:ss or m:ss)" => {
+                    info.wall_seconds = parse_clock(value);
+                }
+                "Maximum resident set size (kbytes)" => {
+                    info.peak_memory_kb = value.parse().ok();
+                }
+                "Exit status" => info.exit_code = value.parse().ok(),
+                _ => {}
+            }
+            if let Some(signal) = line.trim().strip_prefix("Command terminated by signal ") {
+                info.status = Some(format!("signal({})", signal));
+            }
+        }
+        info.cpu_seconds = cpu;
+        info
+    }
+}
+
+impl ResultSource for BenchExec {
+    fn detect(&self, log: &str) -> bool {
+        log.trim_start().starts_with("<?xml") && log.contains("<run ")
+    }
+
+    fn parse(&self, log: &str, task: &str) -> LogInfo {
+        lazy_static! {
+            static ref RUN: Regex =
+                Regex::new("(?s)<run [^>]*name=\"([^\"]*)\"[^>]*>(.*?)</run>").unwrap();
+            static ref COLUMN: Regex =
+                Regex::new("<column [^>]*title=\"([^\"]*)\"[^>]*value=\"([^\"]*)\"").unwrap();
+        }
+        let runs: Vec<(&str, &str)> = RUN
+            .captures_iter(log)
+            .map(|c| (c.get(1).unwrap().as_str(), c.get(2).unwrap().as_str()))
+            .collect();
+        let run = runs
+            .iter()
+            .find(|(name, _)| name.rsplit('/').next() == Some(task))
+            .or(if runs.len() == 1 { runs.first() } else { None });
+        let Some((_, run)) = run else {
+            return LogInfo::default();
+        };
+
+        let mut info = LogInfo::default();
+        let seconds = |v: &str| v.trim_end_matches('s').parse::<f64>().ok();
+        for c in COLUMN.captures_iter(run) {
+            let value = &c[2];
+            match &c[1] {
+                "walltime" => info.wall_seconds = seconds(value),
+                "cputime" => info.cpu_seconds = seconds(value),
+                "memory" => {
+                    info.peak_memory_kb = value
+                        .trim_end_matches('B')
+                        .parse::<u64>()
+                        .ok()
+                        .map(|b| b / 1024)
+                }
+                "returnvalue" => info.exit_code = value.parse().ok(),
+                // Newer versions, e.g. "exit_code=10" or "signal=9".
+                "exitcode" => {
+                    if let Some(code) = value.strip_prefix("exit_code=") {
+                        info.exit_code = code.parse().ok();
+                    } else if let Some(signal) = value.strip_prefix("signal=") {
+                        info.status = Some(format!("signal({})", signal));
+                    } else {
+                        info.exit_code = value.parse().ok();
+                    }
+                }
+                "exitsignal" => info.status = Some(format!("signal({})", value)),
+                "terminationreason" => {
+                    info.status = Some(
+                        match value {
+                            "memory" => "out of memory",
+                            _ => "out of time",
+                        }
+                        .to_string(),
+                    )
+                }
+                "status" => {
+                    let status = if value.starts_with("TIMEOUT") {
+                        Some("out of time".to_string())
+                    } else if value.starts_with("OUT OF MEMORY") {
+                        Some("out of memory".to_string())
+                    } else {
+                        value
+                            .strip_prefix("KILLED BY SIGNAL ")
+                            .map(|s| format!("signal({})", s))
+                    };
+                    info.status = status.or(info.status.take());
+                }
+                _ => {}
+            }
+        }
+        if info.status.is_none() && info.exit_code.is_some() {
+            info.status = Some("ok".to_string());
+        }
+        info
+    }
+}
+
+impl ResultSource for SolverOutput {
+    fn detect(&self, log: &str) -> bool {
+        log.lines().any(|l| answer(l).is_some())
+    }
+
+    fn parse(&self, log: &str, _task: &str) -> LogInfo {
+        LogInfo {
+            answer: log.lines().rev().find_map(answer),
+            ..Default::default()
+        }
+    }
+}
+
+/// Format of the logs to merge.
+#[derive(Debug, Clone, Copy, PartialEq, Default, strum_macros::Display, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Detect the format of every log.
+    #[default]
+    Auto,
+    /// runlim, e.g. from Simsala's submit.pl or the run subcommand.
+    Runlim,
+    /// GNU time with -v.
+    Time,
+    /// BenchExec result XML.
+    Benchexec,
+    /// Plain solver output with "s cnf 0/1" lines.
+    Solver,
+}
+
+/// Naming convention of the logs, matched against the file names in
+/// the working directory.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogPattern {
+    /// A file name with the placeholders {name}, {id} and {orig}, e.g.
+    /// "{name}-{id}:{orig}.log".
+    Pattern(String),
+    /// A regex with the named groups "name" and "id".
+    Regex(String),
+}
+
+impl Default for LogPattern {
+    /// The Simsala convention, e.g. "kissat-3:test.qdimacs.log".
+    fn default() -> Self {
+        LogPattern::Pattern("{name}-{id}:{orig}.log".to_string())
+    }
+}
+
+/// A log found in the working directory. Without a name it belongs to
+/// every solver, without an id to every split, e.g. a BenchExec result
+/// file of a whole run set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFile {
+    pub name: Option<String>,
+    pub id: Option<String>,
+    pub path: PathBuf,
+}
+
+impl LogPattern {
+    fn regex(&self, orig: &str) -> Result<Regex, regex::Error> {
+        lazy_static! {
+            static ref PLACEHOLDER: Regex = Regex::new("\\{(name|id|orig)\\}").unwrap();
+        }
+        let regex = match self {
+            LogPattern::Regex(r) => r.to_owned(),
+            LogPattern::Pattern(p) => {
+                let mut regex = "^".to_string();
+                let mut last = 0;
+                for c in PLACEHOLDER.captures_iter(p) {
+                    let m = c.get(0).unwrap();
+                    regex.push_str(&regex::escape(&p[last..m.start()]));
+                    regex.push_str(&match &c[1] {
+                        "name" => "(?P<name>.+?)".to_string(),
+                        "id" => "(?P<id>\\d+(?:\\.\\d+)*)".to_string(),
+                        _ => regex::escape(orig),
+                    });
+                    last = m.end();
+                }
+                regex.push_str(&regex::escape(&p[last..]));
+                regex + "$"
+            }
+        };
+        Regex::new(&regex)
+    }
+
+    /// Checks that a regex given by the user is valid. A pattern always
+    /// is, as it is escaped.
+    pub fn check(&self) -> Result<(), regex::Error> {
+        self.regex("").map(|_| ())
+    }
+
+    /// All logs of the original file in the working directory. The
+    /// pattern has to be valid, see check.
+    pub fn discover(&self, cwd: &Path, orig: &str) -> Vec<LogFile> {
+        let regex = self.regex(orig).expect("Invalid log pattern!");
+        let mut logs: Vec<LogFile> = FilesNamed::regex(regex.as_str())
+            .within(cwd)
+            .find()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|path| {
+                let c = regex.captures(path.file_name()?.to_str()?)?;
+                Some(LogFile {
+                    name: c.name("name").map(|n| n.as_str().to_owned()),
+                    id: c.name("id").map(|i| i.as_str().to_owned()),
+                    path: path.to_owned(),
+                })
+            })
+            .collect();
+        logs.sort_by(|a, b| a.path.cmp(&b.path));
+        logs
+    }
+}
+
+// In the order of detection, the most specific first.
+const SOURCES: [(LogFormat, &dyn ResultSource); 4] = [
+    (LogFormat::Benchexec, &BenchExec),
+    (LogFormat::Runlim, &Runlim),
+    (LogFormat::Time, &GnuTime),
+    (LogFormat::Solver, &SolverOutput),
+];
+
+/// Reads a log of the given task in the given format.
+pub fn parse_log(log: &str, task: &str, format: LogFormat) -> LogInfo {
+    let source = SOURCES
+        .iter()
+        .find(|(f, s)| *f == format || (format == LogFormat::Auto && s.detect(log)))
+        .map(|(_, s)| *s);
+    let mut info = match source {
+        Some(s) => s.parse(log, task),
+        // Nothing known, e.g. an empty log of a job that never finished.
+        None => LogInfo::default(),
+    };
+    // Printed by the solver, no matter how it was run.
+    info.certificate = certificate(log);
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        let time = "\tCommand being timed: \"kissat 0:f.cnf\"\n\
+                    \tUser time (seconds): 1.50\n\
+                    \tSystem time (seconds): 0.25\n\
+                    \tElapsed (wall clock) time (h:mm:ss or m:ss): 1:02.50\n\
+                    \tMaximum resident set size (kbytes): 2048\n\
+                    \tExit status: 10\n";
+        let info = parse_log(time, "0:f.cnf", LogFormat::Auto);
+        assert_eq!(info.result(), SolverReturnCode::Sat);
+        assert_eq!(info.wall_seconds, Some(62.5));
+        assert_eq!(info.cpu_seconds, Some(1.75));
+        assert_eq!(info.peak_memory_kb, Some(2048));
+
+        let xml = "<?xml version=\"1.0\"?>\n<result>\n\
+                   <run name=\"splits/0:f.cnf\">\n\
+                   <column title=\"status\" value=\"TIMEOUT\"/>\n\
+                   <column title=\"walltime\" value=\"60.1s\"/>\n</run>\n\
+                   <run name=\"splits/1:f.cnf\">\n\
+                   <column title=\"status\" value=\"false\"/>\n\
+                   <column title=\"returnvalue\" value=\"20\"/>\n\
+                   <column title=\"memory\" value=\"2097152B\"/>\n</run>\n</result>\n";
+        let info = parse_log(xml, "0:f.cnf", LogFormat::Auto);
+        assert_eq!(info.result(), SolverReturnCode::Timeout);
+        assert_eq!(info.wall_seconds, Some(60.1));
+        let info = parse_log(xml, "1:f.cnf", LogFormat::Auto);
+        assert_eq!(info.result(), SolverReturnCode::Unsat);
+        assert_eq!(info.peak_memory_kb, Some(2048));
+        assert_eq!(
+            parse_log(xml, "2:f.cnf", LogFormat::Auto).result(),
+            SolverReturnCode::Unknown
+        );
+
+        let output = "c solving\ns cnf 0 3 1\n";
+        assert_eq!(
+            parse_log(output, "0:f.cnf", LogFormat::Auto).result(),
+            SolverReturnCode::Unsat
+        );
+        assert_eq!(
+            parse_log(output, "0:f.cnf", LogFormat::Benchexec).result(),
+            SolverReturnCode::Unknown
+        );
+    }
+
+    #[test]
+    fn test_parse_runlim_metadata() {
+        let log = "c submit.pl: name:  caqe\n\
+                   c submit.pl: task:  0\n\
+                   c submit.pl: commit: fc64fed\n\
+                   c submit.pl: additional args: --qdo\n\
+                   [runlim] host:\t\t\tc1n4\n\
+                   [runlim] real:\t\t\t339.04 seconds\n\
+                   [runlim] time:\t\t\t338.21 seconds\n\
+                   [runlim] space:\t\t\t465044 KB\n\
+                   [runlim] status:\t\tok\n\
+                   [runlim] result:\t\t20\n\
+                   [runlim] load:\t\t\t31.92\n";
+        let info = parse_log(log, "0:f.qdimacs", LogFormat::Auto);
+        assert_eq!(info.result(), SolverReturnCode::Unsat);
+        assert_eq!(info.cpu_seconds, Some(338.21));
+        assert_eq!(info.peak_memory_kb, Some(465044));
+        assert_eq!(info.job.host.as_deref(), Some("c1n4"));
+        assert_eq!(info.job.load, Some(31.92));
+        assert_eq!(info.job.run_name.as_deref(), Some("caqe"));
+        assert_eq!(info.job.task.as_deref(), Some("0"));
+        assert_eq!(info.job.commit.as_deref(), Some("fc64fed"));
+        assert_eq!(info.job.args.as_deref(), Some("--qdo"));
+    }
+
+    #[test]
+    fn test_answer_mismatch() {
+        let log = |output: &str, code: i32| {
+            let log = format!(
+                "{}Command exited with non-zero status {}\n[runlim] status:\t\tok\n",
+                output, code
+            );
+            parse_log(&log, "0:f.qdimacs", LogFormat::Auto).result()
+        };
+        assert_eq!(
+            log("s cnf 0 3 1\nc Unsatisfiable\n", 20),
+            SolverReturnCode::Unsat
+        );
+        assert_eq!(log("s cnf 0 3 1\n", 10), SolverReturnCode::Mismatch);
+        assert_eq!(log("c Satisfiable\n", 20), SolverReturnCode::Mismatch);
+        assert_eq!(log("", 10), SolverReturnCode::Sat);
+        assert_eq!(log("s SATISFIABLE\n", 1), SolverReturnCode::Error);
+
+        let exited = "s SATISFIABLE\n[runlim] status:\t\tok\n[runlim] result:\t\t0\n";
+        assert_eq!(
+            parse_log(exited, "0:f.cnf", LogFormat::Auto).result(),
+            SolverReturnCode::Sat
+        );
+    }
+
+    #[test]
+    fn test_discover_logs() {
+        let dir = std::env::temp_dir().join(format!("logs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for f in [
+            "caqe-0:f.qdimacs.log",
+            "caqe-1.2:f.qdimacs.log",
+            "caqe-f.qdimacs.log",
+        ] {
+            std::fs::write(dir.join(f), "").unwrap();
+        }
+        let logs = LogPattern::default().discover(&dir, "f.qdimacs");
+        let ids: Vec<Option<&str>> = logs.iter().map(|l| l.id.as_deref()).collect();
+        assert_eq!(ids, vec![Some("0"), Some("1.2")]);
+        assert_eq!(logs[0].name.as_deref(), Some("caqe"));
+
+        let shared = LogPattern::Regex("^(?P<name>[a-z]+)-f\\.qdimacs\\.log$".to_string());
+        let logs = shared.discover(&dir, "f.qdimacs");
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].id, None);
+        assert!(LogPattern::Regex("(".to_string()).check().is_err());
+
+        // A shared log holds the splits it mentions, not 1 of "11:".
+        std::fs::write(
+            dir.join("caqe-f.qdimacs.log"),
+            "<run name=\"s/11:f.qdimacs\"></run>\n<run name=\"s/2.1:f.qdimacs\"></run>",
+        )
+        .unwrap();
+        let mut ids: Vec<String> = crate::logged_splits(&logs, "caqe", "f.qdimacs")
+            .into_iter()
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["11", "2.1"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
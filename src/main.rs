@@ -1,10 +1,26 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use qdimacs_splitter::cluster::{parallel_jobs, slurm_script, ScriptOptions};
+use qdimacs_splitter::heuristics::SplitHeuristic;
+use qdimacs_splitter::logs::{LogFormat, LogPattern};
+use qdimacs_splitter::merge::{undecided_nodes, SplitNode};
+use qdimacs_splitter::runner::{
+    relevant_jobs, run_jobs, split_jobs, state_path, stdin_jobs, Job, RunOptions, Solver,
+};
+use qdimacs_splitter::statistics::{
+    csv, produce_statistics_from_run, splits_csv, RunStatistics, SolveStatistics,
+};
+use qdimacs_splitter::strategy::strategy;
 use qdimacs_splitter::{
-    extract_result_from_file, extract_results_from_files, parse_qdimacs, write_qdimacs, Formula,
-    IntegerSplit, SolverResult, SolverReturnCode,
+    count_results, extract_result_from_file, extract_results_from_files, id_list, logged_splits,
+    parse_qdimacs, write_qdimacs, Formula, ProbeResult, ResultCollector, SolverResult,
+    SolverReturnCode, SplitOptions,
 };
 
 /// Tool to explore a QBF formula together with a QBF solver to aid
@@ -18,238 +34,322 @@ struct Args {
     /// Original input file to merge together. Also requires the splitting depth and name of the run.
     #[arg(short, long)]
     orig: Option<String>,
+    /// Merge every run of every original file in the working directory
+    /// that has split logs, with the solver names and depths inferred
+    /// unless given, and print a summary table.
+    #[arg(long, default_value_t = false)]
+    batch: bool,
     /// Name of the run to merge.
     #[arg(short, long, num_args = 1.., value_delimiter = ',')]
     name: Option<Vec<String>>,
+    /// Format of the logs to merge. By default, it is detected for every
+    /// log.
+    #[arg(long, value_enum, default_value_t = LogFormat::Auto)]
+    log_format: LogFormat,
+    /// File names of the logs to merge, with the placeholders {name},
+    /// {id} and {orig}. Without {id}, all splits are read from the same
+    /// log, e.g. a BenchExec result file.
+    #[arg(long, default_value = "{name}-{id}:{orig}.log")]
+    log_pattern: String,
+    /// Regex matching the file names of the logs to merge instead of
+    /// --log-pattern, with the named groups "name" and "id".
+    #[arg(long)]
+    log_regex: Option<String>,
     /// Directory to search files to merge or to write files to. Is the current working directory by default.
     #[arg(short, long)]
     working_directory: Option<String>,
-    /// Depth to split into. Also required for merging files to see how many files to parse.
-    #[arg(short, long, default_value_t = 4)]
-    depth: u32,
+    /// Depth to split into, 4 by default. When merging, it is inferred
+    /// from the ids of the logs if not given.
+    #[arg(short, long)]
+    depth: Option<u32>,
+    /// Heuristic to select split variables of DIMACS files without
+    /// embedded splits, or to order splits inside of quantifier blocks.
+    /// At most the 64 best variables are selected. Lookahead runs a full
+    /// unit propagation of both polarities of every candidate, which is
+    /// slow on large formulas. Must be the same for splitting and
+    /// merging.
+    #[arg(long, value_enum, default_value_t = SplitHeuristic::Prefix)]
+    heuristic: SplitHeuristic,
+    /// Probe splits using unit propagation and skip the ones that are
+    /// already decided. Must be the same for splitting and merging.
+    #[arg(long, default_value_t = false)]
+    probe: bool,
+    /// Universal quantifier blocks (1 is the outermost block) to expand
+    /// in place instead of splitting them. Splitting stops at the first
+    /// expanded split. Must be the same for splitting and merging.
+    #[arg(long, num_args = 1.., value_delimiter = ',')]
+    expand: Vec<usize>,
+    /// Additional split on a clause of existential literals, given as
+    /// comma separated literals, e.g. "1,-2,3". Produces the disjoint
+    /// branches 1; -1 & -2; -1 & 2 & 3. Can be given multiple times.
+    /// Must be the same for splitting and merging.
+    #[arg(long, allow_hyphen_values = true)]
+    clause_split: Vec<String>,
+    /// Number of random XOR constraints to split on, producing 2^k
+    /// splits on their parities. Must be the same for splitting and
+    /// merging.
+    #[arg(long, default_value_t = 0)]
+    xor: usize,
+    /// Variables of the XOR constraints. All variables of a DIMACS file
+    /// or of the outermost existential block of a QBF by default.
+    #[arg(long, num_args = 1.., value_delimiter = ',')]
+    xor_vars: Vec<i32>,
+    /// Seed for generating the XOR constraints.
+    #[arg(long, default_value_t = 0)]
+    xor_seed: u64,
+    /// Additional depth of each generation of re-splitting timed out
+    /// splits. Must be the same for splitting and merging.
+    #[arg(long, default_value_t = 2)]
+    resplit_depth: u32,
+    /// Instead of merging, split the timed out splits that still
+    /// matter for the result deeper. The new splits are written to the
+    /// working directory, their results are merged into the same run.
+    #[arg(long, default_value_t = false)]
+    resplit: bool,
+    /// Split only below the given comma separated literals, e.g.
+    /// "3,-5". Must be the same for splitting and merging.
+    #[arg(long, num_args = 1.., value_delimiter = ',', allow_hyphen_values = true)]
+    under: Vec<i32>,
+    /// Split only below the split with the given id (e.g. "3" or
+    /// "3.1") of a run with the same options at --under-depth.
+    #[arg(long)]
+    under_split: Option<String>,
+    /// Depth of the run --under-split refers to. Is --depth by default.
+    #[arg(long)]
+    under_depth: Option<u32>,
+    /// Print the decision tree of the winning strategy after merging,
+    /// i.e. the winning moves of the winner and all moves of the
+    /// opponent.
+    #[arg(long, default_value_t = false)]
+    strategy: bool,
+    /// Write the decision tree of the winning strategy as JSON.
+    #[arg(long)]
+    strategy_json: Option<PathBuf>,
+    /// Write the statistics of the merged runs as JSON, including the
+    /// result of every split.
+    #[arg(long)]
+    stats_json: Option<PathBuf>,
+    /// Write the statistics of the merged runs as CSV, one line per
+    /// run. The hosts and the splits are only in the JSON and
+    /// --splits-csv.
+    #[arg(long)]
+    stats_csv: Option<PathBuf>,
+    /// Write the result of every split of the merged runs as CSV, one
+    /// line per split keyed by the instance, depth and solvers.
+    #[arg(long)]
+    splits_csv: Option<PathBuf>,
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Runs a solver on all splits of the original file in the working
+    /// directory, writes runlim compatible logs and merges them. Takes
+    /// the same options as merging, e.g.
+    /// `--orig f.qdimacs --name kissat run -j 8 -t 60 -- kissat -q`.
+    /// Several solvers separated by ":::" are run as a portfolio.
+    Run(RunArgs),
+    /// Writes job scripts solving all splits of the original file in the
+    /// working directory on a cluster, with logs named as merging
+    /// expects, e.g. `--orig f.qdimacs --name kissat script --slurm
+    /// f.sbatch -t 600 -- kissat -q`. Splits that already have a log are
+    /// skipped.
+    Script(ScriptArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct ScriptArgs {
+    /// Write a Slurm array job script with one task per split.
+    #[arg(long)]
+    slurm: Option<PathBuf>,
+    /// Write a GNU parallel job list with one line per split, to be run
+    /// in the working directory.
+    #[arg(long)]
+    parallel: Option<PathBuf>,
+    /// Wall clock timeout of each solver in seconds.
+    #[arg(short, long)]
+    timeout: Option<f64>,
+    /// Memory limit of each solver in MB.
+    #[arg(short, long)]
+    memory_limit: Option<u64>,
+    /// CPU time limit of each solver in seconds.
+    #[arg(long)]
+    cpu_limit: Option<u64>,
+    /// The runlim binary.
+    #[arg(long, default_value = "runlim")]
+    runlim: String,
+    /// Slurm partition to submit to.
+    #[arg(long)]
+    partition: Option<String>,
+    /// Maximum number of Slurm array tasks running at the same time.
+    #[arg(long)]
+    max_parallel: Option<usize>,
+    /// Solver command line. The split file replaces "{}", otherwise it
+    /// is appended.
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    solver: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    /// Number of solvers to run in parallel. All cores by default.
+    #[arg(short, long)]
+    jobs: Option<usize>,
+    /// Wall clock timeout of each solver in seconds.
+    #[arg(short, long)]
+    timeout: Option<f64>,
+    /// Address space limit of each solver in MB.
+    #[arg(short, long)]
+    memory_limit: Option<u64>,
+    /// CPU time limit of each solver in seconds.
+    #[arg(long)]
+    cpu_limit: Option<u64>,
+    /// Run all splits, even if the results of others already decide
+    /// them. By default, such splits are cancelled.
+    #[arg(long, default_value_t = false)]
+    no_cancel: bool,
+    /// Stream the splits of the first generation to the solver's stdin
+    /// instead of reading written split files. "{}" in the solver
+    /// command line is replaced by /dev/stdin. Re-splits are written
+    /// files, so they are rejected.
+    #[arg(long, default_value_t = false)]
+    stdin: bool,
+    /// Start over, instead of resuming an interrupted run from its
+    /// state file.
+    #[arg(long, default_value_t = false)]
+    restart: bool,
+    /// Solver command line. The split file replaces "{}", otherwise it
+    /// is appended. A portfolio of solvers is separated by ":::", with
+    /// one name each.
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    solver: Vec<String>,
 }
 
 fn get_current_working_dir() -> std::io::Result<PathBuf> {
     std::env::current_dir()
 }
 
+/// Writes the splits with the given indices of a generation. The first
+/// generation writes all splits.
 fn process_formula_splits(
     formula: &Formula,
-    depth: u32,
+    options: &SplitOptions,
+    generation: u32,
+    indices: &[usize],
     filename: &str,
     working_directory: &Path,
     verbose: bool,
 ) {
+    let depth = Formula::generation_depth(options, generation);
     let splits = formula.produce_splits(depth);
+    let probes = if options.probe {
+        formula.probe_splits(depth)
+    } else {
+        vec![ProbeResult::Open; splits.len()]
+    };
+    let probes: Vec<ProbeResult> = indices.iter().map(|i| probes[*i]).collect();
 
-    for i in 0..splits.len() {
-        let mut assumed_f: Formula = Clone::clone(formula);
-        for j in 0..splits[i].len() {
-            let v = &splits[i][j];
-            // Flip forall quantifiers to existential if there is a specific assignment.
-            if !assumed_f.prefix.is_empty() && assumed_f.prefix[j] > 0 {
-                assumed_f.prefix[j] = -assumed_f.prefix[j];
+    for (i, probe) in std::iter::zip(indices.iter().copied(), probes.iter()) {
+        let split = &splits[i];
+        if *probe != ProbeResult::Open {
+            if verbose {
+                println!("Skipping split with variables {:?}: {:?}", split, probe);
             }
-            assumed_f.matrix.push(vec![*v]);
-            assumed_f.nr_of_clauses += 1;
+            continue;
         }
+        let assumed_f = formula.split_formula(depth, split);
         let path = Path::new(filename);
-        let out_path_string = i.to_string() + ":" + path.file_name().unwrap().to_str().unwrap();
+        let out_path_string = formula.split_id(options, generation, i)
+            + ":"
+            + path.file_name().unwrap().to_str().unwrap();
         let mut out_path = PathBuf::new();
         out_path.push(working_directory);
         out_path.push(out_path_string);
         if verbose {
             println!(
                 "Split with variables {:?} into {:?}",
-                splits[i],
+                split,
                 out_path.as_path()
             );
         }
         write_qdimacs(out_path.as_path(), &assumed_f).unwrap();
     }
-}
-
-#[derive(Debug)]
-struct SolveStatistics {
-    pub minimal_execution_time_seconds: f64,
-    pub summed_execution_time_seconds: f64,
-    pub non_split_execution_time_seconds: f64,
-    pub speedup_against_non_split: f64,
-    pub required_cores: i32,
-    pub result: SolverReturnCode,
-    pub naive_split_count: i32,
-    pub run_tasks_compared_to_naive: f64,
-}
-
-enum Quantifier {
-    Forall,
-    Exists,
-}
-
-// Reduce the result by one layer.
-fn reduce_result(
-    quant: Quantifier,
-    single_layer_width: usize,
-    results: Vec<SolverResult>,
-) -> Vec<SolverResult> {
-    assert!(single_layer_width > 0);
-    let result_count = results.len() / single_layer_width;
-    (0..result_count)
-        .map(|x| {
-            let begin = x * single_layer_width;
-            let end = (x + 1) * single_layer_width;
-
-            let resit = || (begin..end).map(|x| &results[x]);
-            let min_of = |compare_against: SolverReturnCode| -> &SolverResult {
-                resit()
-                    .filter(|x| x.result == compare_against)
-                    .min_by(|a, b| {
-                        a.wall_seconds
-                            .partial_cmp(&b.wall_seconds)
-                            .expect("Tried to compare a NaN")
-                    })
-                    .unwrap()
-            };
-            let max_of = |compare_against: SolverReturnCode| -> &SolverResult {
-                resit()
-                    .filter(|x| x.result == compare_against)
-                    .max_by(|a, b| {
-                        a.wall_seconds
-                            .partial_cmp(&b.wall_seconds)
-                            .expect("Tried to compare a NaN")
-                    })
-                    .unwrap()
-            };
 
-            if matches!(quant, Quantifier::Exists) {
-                if resit().any(|r| matches!(r.result, SolverReturnCode::Sat)) {
-                    let min = min_of(SolverReturnCode::Sat);
-                    SolverResult {
-                        wall_seconds: min.wall_seconds,
-                        result: SolverReturnCode::Sat,
-                        name: min.name.to_owned(),
-                    }
-                } else {
-                    if resit().all(|r| matches!(r.result, SolverReturnCode::Unsat)) {
-                        let max = max_of(SolverReturnCode::Unsat);
-                        SolverResult {
-                            wall_seconds: max.wall_seconds,
-                            result: SolverReturnCode::Unsat,
-                            name: max.name.to_owned(),
-                        }
-                    } else {
-                        SolverResult {
-                            wall_seconds: 10000000.0,
-                            result: SolverReturnCode::Timeout,
-                            name: "(no solver)".to_string(),
-                        }
-                    }
-                }
-            } else {
-                if resit().all(|r| matches!(r.result, SolverReturnCode::Sat)) {
-                    let max = max_of(SolverReturnCode::Sat);
-                    SolverResult {
-                        wall_seconds: max.wall_seconds,
-                        result: SolverReturnCode::Sat,
-                        name: max.name.to_owned(),
-                    }
-                } else {
-                    if resit().any(|r| matches!(r.result, SolverReturnCode::Unsat)) {
-                        let min = min_of(SolverReturnCode::Unsat);
-                        SolverResult {
-                            wall_seconds: min.wall_seconds,
-                            result: SolverReturnCode::Unsat,
-                            name: min.name.to_owned(),
-                        }
-                    } else {
-                        SolverResult {
-                            wall_seconds: 10000000.0,
-                            result: SolverReturnCode::Timeout,
-                            name: "(no solver)".to_string(),
-                        }
-                    }
-                }
-            }
-        })
-        .collect()
-}
-
-fn quant_from_prefix(formula: &Formula, pos: usize) -> Quantifier {
-    if pos >= formula.prefix.len() {
-        // This case is important for merging DIMACS files.
-        Quantifier::Exists
-    } else if formula.prefix[pos] < 0 {
-        Quantifier::Exists
-    } else {
-        Quantifier::Forall
+    if options.probe {
+        let refuted = probes
+            .iter()
+            .filter(|p| **p == ProbeResult::Refuted)
+            .count();
+        let irrelevant = probes
+            .iter()
+            .filter(|p| **p == ProbeResult::Irrelevant)
+            .count();
+        println!(
+            "Probing avoided {} of {} splits ({} refuted, {} irrelevant)",
+            refuted + irrelevant,
+            indices.len(),
+            refuted,
+            irrelevant
+        );
     }
 }
 
-fn produce_statistics_from_run(
+/// Splits the timed out splits that still matter for the result one
+/// generation deeper.
+fn process_formula_resplits(
     formula: &Formula,
-    results: &[SolverResult],
-    split_count: u64,
-    og_formula_result: Option<SolverResult>,
-) -> SolveStatistics {
-    let splits: Vec<&IntegerSplit> = formula.splits[0..split_count as usize]
+    options: &SplitOptions,
+    nodes: &[SplitNode],
+    filename: &str,
+    working_directory: &Path,
+    verbose: bool,
+) {
+    let leaves: Vec<&SplitNode> = undecided_nodes(formula, options, nodes)
         .into_iter()
-        .rev()
+        .filter(|n| n.children.is_empty())
         .collect();
-
-    // The splits at this point contain all results in full detail.
-    // Each element in the vector maps to some problem instance that
-    // was split from the original formula.
-
-    let splits_depth: usize = splits.iter().map(|x| x.vars.len()).sum();
-
-    let base: i32 = 2;
-    let naive_split_count = base.pow(splits_depth as u32);
-
-    let required_cores = results.len() as i32;
-
-    let summed_execution_time_seconds: f64 = results.iter().map(|x| x.wall_seconds).sum();
-
-    // This is not influenced by the \eta reordering, as the reorder
-    // happens only locally to each quantifier block and the
-    // quantifier block order doesn't change.
-    let mut quanttree_pos = splits_depth - 1;
-    let mut solver_results: Vec<SolverResult> = results.to_vec();
-    for s in splits.into_iter() {
-        let n = s.nr_of_splits();
-        solver_results = reduce_result(
-            quant_from_prefix(&formula, quanttree_pos),
-            n,
-            solver_results,
-        );
-        if quanttree_pos > s.vars.len() {
-            quanttree_pos -= s.vars.len();
-        }
-    }
-
-    assert!(solver_results.len() == 1);
-
-    let minimal_execution_time_seconds: f64 = solver_results[0].wall_seconds;
-
-    let mut non_split_execution_time_seconds: f64 = 10000000.0;
-    let mut speedup_against_non_split: f64 = 0.0;
-
-    if let Some(r) = og_formula_result {
-        non_split_execution_time_seconds = r.wall_seconds;
-        speedup_against_non_split =
-            non_split_execution_time_seconds / minimal_execution_time_seconds;
+    if leaves.is_empty() {
+        println!("No timed out splits left that matter for the result.");
+        return;
     }
 
-    SolveStatistics {
-        minimal_execution_time_seconds,
-        summed_execution_time_seconds,
-        required_cores,
-        result: solver_results[0].result,
-        non_split_execution_time_seconds,
-        speedup_against_non_split,
-        naive_split_count,
-        run_tasks_compared_to_naive: required_cores as f64 / naive_split_count as f64,
+    let generations = leaves.iter().map(|n| n.generation).max().unwrap();
+    for g in 0..=generations {
+        let parents: Vec<&&SplitNode> = leaves.iter().filter(|n| n.generation == g).collect();
+        if parents.is_empty() {
+            continue;
+        }
+        if !formula.can_resplit(options, g) {
+            println!(
+                "{} timed out splits of generation {} cannot be split deeper.",
+                parents.len(),
+                g
+            );
+            continue;
+        }
+        let width = formula.generation_width(options, g + 1);
+        let indices: Vec<usize> = parents
+            .iter()
+            .flat_map(|n| n.index * width..(n.index + 1) * width)
+            .collect();
+        println!(
+            "Re-splitting {} timed out splits of generation {} into {} splits.",
+            parents.len(),
+            g,
+            indices.len()
+        );
+        process_formula_splits(
+            formula,
+            options,
+            g + 1,
+            &indices,
+            filename,
+            working_directory,
+            verbose,
+        );
     }
 }
 
@@ -258,78 +358,755 @@ fn main() {
 
     let working_directory: PathBuf = args
         .working_directory
-        .and_then(|x| {
+        .map(|x| {
             let mut b = PathBuf::new();
             b.push(x);
-            Some(b)
+            b
         })
         .unwrap_or_else(|| get_current_working_dir().unwrap());
 
-    if args.split.is_some() {
-        let filename = args.split.unwrap();
+    let mut options = SplitOptions {
+        depth: args.depth.unwrap_or(4),
+        heuristic: args.heuristic,
+        probe: args.probe,
+        expand: args.expand,
+        clause_splits: args
+            .clause_split
+            .iter()
+            .map(|c| {
+                c.split(',')
+                    .map(|l| {
+                        l.trim()
+                            .parse::<i32>()
+                            .expect("Invalid clause split literal!")
+                    })
+                    .collect()
+            })
+            .collect(),
+        xor: args.xor,
+        xor_vars: args.xor_vars,
+        xor_seed: args.xor_seed,
+        resplit_depth: args.resplit_depth,
+        under: args.under,
+    };
+    let logs = LogOptions {
+        format: args.log_format,
+        pattern: match args.log_regex {
+            Some(regex) => LogPattern::Regex(regex),
+            None => LogPattern::Pattern(args.log_pattern),
+        },
+    };
+    if let Err(e) = logs.pattern.check() {
+        println!("!! Invalid --log-regex !!\n{}", e);
+        return;
+    }
+    // Without --depth, merging infers the depth from the logs.
+    if args.split.is_some() || args.depth.is_some() {
+        if let Err(e) = options.check() {
+            println!("!! {} !!", e);
+            return;
+        }
+    }
+    let statistics_output = StatisticsOutput {
+        json: args.stats_json,
+        csv: args.stats_csv,
+        splits_csv: args.splits_csv,
+    };
+
+    if let Some(id) = &args.under_split {
+        let filename = args
+            .split
+            .as_ref()
+            .or(args.orig.as_ref())
+            .expect("--under-split requires --split or --orig!");
+        let formula_str = fs::read_to_string(filename).unwrap();
+        let mut formula = parse_qdimacs(&formula_str, false).unwrap();
+        let run_options = SplitOptions {
+            depth: args.under_depth.unwrap_or(options.depth),
+            ..options.clone()
+        };
+        if let Err(e) = run_options.check() {
+            println!("!! {} !!", e);
+            return;
+        }
+        formula.apply_split_options(&run_options, false);
+        let cube = formula.split_cube(&run_options, id);
+        if args.verbose {
+            println!("Split {} has the assumptions {:?}", id, cube);
+        }
+        options.under.extend(cube);
+    }
+
+    if let Some(filename) = args.split {
         let formula_str = fs::read_to_string(&filename).unwrap();
-        let formula = parse_qdimacs(&formula_str, args.verbose).unwrap();
+        let mut formula = parse_qdimacs(&formula_str, args.verbose).unwrap();
+        formula.apply_split_options(&options, args.verbose);
+        let indices: Vec<usize> = (0..formula.produce_splits(options.depth).len()).collect();
         process_formula_splits(
             &formula,
-            args.depth,
+            &options,
+            0,
+            &indices,
             &filename,
             working_directory.as_path(),
             args.verbose,
         );
-    } else if args.orig.is_some() && args.name.is_some() {
+    } else if let Some(orig) = args.orig {
         let cwd = working_directory.as_path();
-        let orig = args.orig.unwrap();
-        let name = args.name.unwrap();
 
         let orig_path = Path::new(&orig);
         if !orig_path.exists() {
             println!("!! Original File {} does not exist !!", orig);
-        } else {
-            let (formula, results) = extract_results_from_files(&orig_path, &name, args.depth, cwd);
-            let (_rounded_depth, split_count) =
-                formula.embedded_splits_round_fitting(args.depth as i64);
-
-            let mut orig_file_result = PathBuf::new();
-            orig_file_result.push(cwd);
-            orig_file_result.push(
-                name[0].to_owned()
-                    + "-"
-                    + orig_path.file_name().unwrap().to_str().unwrap()
-                    + ".log",
-            );
-
-            let og_formula_result: Option<SolverResult> = if orig_file_result.exists() {
-                Some(extract_result_from_file(
-                    orig_file_result.as_path(),
-                    &name[0],
-                ))
-            } else {
-                None
+            return;
+        }
+        let runs = infer_runs(orig_path, cwd, &logs, args.name, args.depth, &options);
+        if runs.is_empty() {
+            println!("!! Require --name, there are no logs of {} !!", orig);
+            return;
+        }
+        if runs.len() > 1
+            && (args.command.is_some() || args.resplit || args.strategy_json.is_some())
+        {
+            println!("!! Found logs of several runs, select one using --name !!");
+            return;
+        }
+        if args.resplit && statistics_output.is_some() {
+            println!("!! Re-splitting writes no statistics, merge again without --resplit !!");
+            return;
+        }
+        if let Some(Command::Script(script)) = args.command {
+            write_scripts(orig_path, &runs[0], cwd, &logs, script);
+            return;
+        }
+        if let Some(Command::Run(run)) = args.command {
+            let Run { name, options } = &runs[0];
+            let formula_str = fs::read_to_string(orig_path).unwrap();
+            let mut formula = parse_qdimacs(&formula_str, false).unwrap();
+            formula.apply_split_options(options, false);
+            let formula = Arc::new(formula);
+            let resplits = || {
+                split_jobs(&formula, options, orig_path, name, cwd)
+                    .iter()
+                    .any(|j| j.id.contains('.'))
             };
-
-            let statistics =
-                produce_statistics_from_run(&formula, &results, split_count, og_formula_result);
-            println!("Statistics: minimal execution path: {} , summed execution time: {} , required cores: {} , result: {}, naive split count: {} (compared to naive splits: {})",
-                     statistics.minimal_execution_time_seconds,
-                     statistics.summed_execution_time_seconds,
-                     statistics.required_cores,
-                     statistics.result,
-                     statistics.naive_split_count,
-                     statistics.run_tasks_compared_to_naive);
-            if orig_file_result.exists() {
+            if run.stdin && (args.resplit || resplits()) {
                 println!(
-                    "Original solve time: {} gives speedup of {}",
-                    statistics.non_split_execution_time_seconds,
-                    statistics.speedup_against_non_split
+                    "!! --stdin only streams the first generation, run re-splits without it !!"
+                );
+                return;
+            }
+            let commands: Vec<&[String]> = run.solver.split(|a| a == ":::").collect();
+            if commands.len() > 1 && commands.len() != name.len() {
+                panic!(
+                    "A portfolio of {} solvers requires as many names!",
+                    commands.len()
                 );
+            }
+            let run_options = RunOptions {
+                solvers: std::iter::zip(name, commands)
+                    .map(|(name, command)| Solver {
+                        name: name.to_owned(),
+                        command: command.to_vec(),
+                    })
+                    .collect(),
+                jobs: run.jobs.unwrap_or_else(|| {
+                    std::thread::available_parallelism()
+                        .map(|n| n.get())
+                        .unwrap_or(1)
+                }),
+                timeout: run.timeout,
+                memory_limit: run.memory_limit,
+                cpu_limit: run.cpu_limit,
+                state: Some(state_path(orig_path, &name[0], cwd)),
+            };
+            let state = run_options.state.as_ref().unwrap();
+            if run.restart && state.exists() {
+                fs::remove_file(state).unwrap();
+            }
+            let names: Vec<String> = run_options
+                .solvers
+                .iter()
+                .map(|s| s.name.to_owned())
+                .collect();
+            let jobs = if run.stdin {
+                stdin_jobs(formula.clone(), options, orig_path, &names, cwd)
             } else {
-                println!(
-                    "No statistics compared to non-split solving, as file {:?} not found.",
-                    orig_file_result
-                )
+                split_jobs(&formula, options, orig_path, &names, cwd)
+            };
+            println!(
+                "Running {} splits using {} jobs",
+                jobs.len(),
+                run_options.jobs
+            );
+            let mut collector = ResultCollector::new(&formula, options);
+            let mut relevant = |results: &[Option<SolverResult>]| -> Vec<bool> {
+                if run.no_cancel {
+                    vec![true; results.len()]
+                } else {
+                    relevant_jobs(&mut collector, &formula, options, &jobs, results)
+                }
+            };
+            run_jobs(&jobs, &run_options, &mut relevant);
+        }
+        let output = MergeOutput {
+            verbose: args.verbose,
+            strategy: args.strategy,
+            strategy_json: args.strategy_json,
+        };
+        let mut statistics = vec![];
+        for run in runs {
+            if let Some(s) = merge(
+                orig_path,
+                &run.name,
+                &run.options,
+                cwd,
+                &logs,
+                args.resplit,
+                &output,
+            ) {
+                statistics.push(RunStatistics {
+                    instance: orig_path.file_name().unwrap().to_str().unwrap().to_owned(),
+                    depth: run.options.depth,
+                    solvers: run.name,
+                    statistics: s,
+                });
+            }
+        }
+        write_statistics(&statistics, &statistics_output);
+    } else if args.batch {
+        if args.strategy_json.is_some() {
+            println!("!! --strategy-json requires a single run, merge it using --orig !!");
+            return;
+        }
+        let runs = merge_batch(
+            working_directory.as_path(),
+            args.name,
+            args.depth,
+            &options,
+            &logs,
+            &MergeOutput {
+                verbose: args.verbose,
+                strategy: args.strategy,
+                strategy_json: None,
+            },
+        );
+        write_statistics(&runs, &statistics_output);
+    } else {
+        println!("!! Require either --split, --orig or --batch !!");
+    }
+}
+
+/// Where the statistics of the merged runs are written to.
+struct StatisticsOutput {
+    json: Option<PathBuf>,
+    csv: Option<PathBuf>,
+    splits_csv: Option<PathBuf>,
+}
+
+impl StatisticsOutput {
+    fn is_some(&self) -> bool {
+        self.json.is_some() || self.csv.is_some() || self.splits_csv.is_some()
+    }
+}
+
+/// Writes the statistics of the merged runs, see RunStatistics.
+fn write_statistics(runs: &[RunStatistics], output: &StatisticsOutput) {
+    let write = |path: &Option<PathBuf>, content: &dyn Fn() -> String| {
+        if let Some(path) = path {
+            fs::write(path, content()).expect("Statistics could not be written!");
+            println!("Wrote the statistics to {:?}", path);
+        }
+    };
+    write(&output.json, &|| {
+        serde_json::to_string_pretty(runs).unwrap()
+    });
+    write(&output.csv, &|| csv(runs));
+    write(&output.splits_csv, &|| splits_csv(runs));
+}
+
+/// A run to merge, i.e. a set of solvers and a depth.
+struct Run {
+    name: Vec<String>,
+    options: SplitOptions,
+}
+
+/// The runs of the original file. Without given names, every solver
+/// with logs is a run of its own, and without a given depth, the depth
+/// of every run is inferred from its logs. Empty if there are no logs
+/// to infer the names from.
+fn infer_runs(
+    orig_path: &Path,
+    cwd: &Path,
+    logs: &LogOptions,
+    name: Option<Vec<String>>,
+    depth: Option<u32>,
+    options: &SplitOptions,
+) -> Vec<Run> {
+    let orig = orig_path.file_name().unwrap().to_str().unwrap();
+    let found = logs.pattern.discover(cwd, orig);
+    let names: Vec<Vec<String>> = match name {
+        Some(name) => vec![name],
+        None => {
+            let mut names: Vec<String> = found.iter().filter_map(|l| l.name.clone()).collect();
+            names.sort_unstable();
+            names.dedup();
+            if !names.is_empty() {
+                println!("Found logs of {}", names.join(", "));
+            }
+            names.into_iter().map(|n| vec![n]).collect()
+        }
+    };
+    let mut formula: Option<Formula> = None;
+    names
+        .into_iter()
+        .map(|name| {
+            let mut options = options.clone();
+            if let Some(depth) = depth {
+                options.depth = depth;
+                return Run { name, options };
+            }
+            let logged: HashSet<String> = name
+                .iter()
+                .flat_map(|n| logged_splits(&found, n, orig))
+                .collect();
+            let count = logged.iter().filter_map(|id| id.parse::<usize>().ok()).max();
+            if let Some(count) = count {
+                let formula = formula.get_or_insert_with(|| {
+                    parse_qdimacs(&fs::read_to_string(orig_path).unwrap(), false).unwrap()
+                });
+                let mut formula = formula.clone();
+                formula.apply_split_options(&options, false);
+                if let Some(depth) = formula.depth_for_splits(count + 1) {
+                    options.depth = depth;
+                    println!(
+                        "Inferred depth {} of {} from the logs",
+                        depth,
+                        name.join(", ")
+                    );
+                    // Splits decided by probing are never written.
+                    let probes = options.probe.then(|| formula.probe_splits(depth));
+                    let missing: Vec<String> = (0..formula.produce_splits(depth).len())
+                        .filter(|i| probes.as_ref().is_none_or(|p| p[*i] == ProbeResult::Open))
+                        .map(|i| formula.split_id(&options, 0, i))
+                        .filter(|id| !logged.contains(id))
+                        .collect();
+                    if !missing.is_empty() {
+                        let missing: Vec<&str> = missing.iter().map(|id| id.as_str()).collect();
+                        println!(
+                            "  {} of its splits have no log, give --depth if the last ones are missing: {}",
+                            missing.len(),
+                            id_list(&missing)
+                        );
+                    }
+                }
+            }
+            Run { name, options }
+        })
+        .collect()
+}
+
+/// Merges every run of every original file in the working directory
+/// that has split logs, i.e. every file except the logs and the splits,
+/// and prints a summary table of all of them. Returns the statistics of
+/// all runs.
+fn merge_batch(
+    cwd: &Path,
+    name: Option<Vec<String>>,
+    depth: Option<u32>,
+    options: &SplitOptions,
+    logs: &LogOptions,
+    output: &MergeOutput,
+) -> Vec<RunStatistics> {
+    lazy_static! {
+        static ref SPLIT: Regex = Regex::new(r"^\d+(\.\d+)*:").unwrap();
+    }
+    let mut originals: Vec<PathBuf> = fs::read_dir(cwd)
+        .expect("Working directory could not be read!")
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            let file = p.file_name().unwrap().to_str().unwrap_or(".log");
+            !file.ends_with(".log") && !SPLIT.is_match(file)
+        })
+        .filter(|p| {
+            let file = p.file_name().unwrap().to_str().unwrap();
+            logs.pattern
+                .discover(cwd, file)
+                .iter()
+                .any(|l| l.id.is_some())
+        })
+        .collect();
+    originals.sort();
+    if originals.is_empty() {
+        println!("!! No split logs found in {:?} !!", cwd);
+        return vec![];
+    }
+
+    let mut runs = vec![];
+    for orig_path in originals.iter() {
+        let file = orig_path.file_name().unwrap().to_str().unwrap();
+        println!("== {} ==", file);
+        for run in infer_runs(orig_path, cwd, logs, name.clone(), depth, options) {
+            println!(
+                "-- {} at depth {} --",
+                run.name.join(", "),
+                run.options.depth
+            );
+            let statistics = merge(orig_path, &run.name, &run.options, cwd, logs, false, output);
+            if let Some(statistics) = statistics {
+                runs.push(RunStatistics {
+                    instance: file.to_owned(),
+                    depth: run.options.depth,
+                    solvers: run.name,
+                    statistics,
+                });
+            }
+        }
+    }
+    print!("{}", summary_table(&runs));
+    runs
+}
+
+/// One line per merged run, with aligned columns.
+fn summary_table(runs: &[RunStatistics]) -> String {
+    let optional = |v: Option<f64>| v.map_or("-".to_string(), |v| format!("{:.2}", v));
+    // Exact values, or their bounds if not decided.
+    let bounded = |exact: Option<f64>, lower: f64, upper: Option<f64>| match (exact, upper) {
+        (Some(v), Some(u)) if v == lower && v == u => format!("{:.2}", v),
+        (_, Some(u)) => format!("{:.2}..{:.2}", lower, u),
+        (_, None) => format!(">= {:.2}", lower),
+    };
+    let mut lines: Vec<Vec<String>> = vec![[
+        "instance",
+        "solvers",
+        "depth",
+        "result",
+        "minimal path",
+        "summed time",
+        "cores",
+        "original time",
+        "speedup",
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect()];
+    for run in runs.iter() {
+        let s = &run.statistics;
+        lines.push(vec![
+            run.instance.to_owned(),
+            run.solvers.join(","),
+            run.depth.to_string(),
+            s.result.to_string(),
+            bounded(
+                Some(s.minimal_execution_time_seconds),
+                s.minimal_execution_time_lower_seconds,
+                s.minimal_execution_time_upper_seconds,
+            ),
+            format!("{:.2}", s.summed_execution_time_seconds),
+            s.required_cores.to_string(),
+            optional(s.non_split_execution_time_seconds),
+            match s.speedup_lower {
+                Some(lower) => bounded(s.speedup_against_non_split, lower, s.speedup_upper),
+                None => "-".to_string(),
+            },
+        ]);
+    }
+    let widths: Vec<usize> = (0..lines[0].len())
+        .map(|c| lines.iter().map(|l| l[c].len()).max().unwrap())
+        .collect();
+    let mut table = String::new();
+    for line in lines.iter() {
+        let cells: Vec<String> = std::iter::zip(line, &widths)
+            .enumerate()
+            .map(|(c, (cell, w))| {
+                // The instance, solvers and result are text, the rest
+                // numbers.
+                if c <= 1 || c == 3 {
+                    format!("{:<w$}", cell, w = w)
+                } else {
+                    format!("{:>w$}", cell, w = w)
+                }
+            })
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+fn write_scripts(orig_path: &Path, run: &Run, cwd: &Path, logs: &LogOptions, script: ScriptArgs) {
+    if script.slurm.is_none() && script.parallel.is_none() {
+        println!("!! Require --slurm or --parallel !!");
+        return;
+    }
+    let name = &run.name[0];
+    let orig = orig_path.file_name().unwrap().to_str().unwrap();
+    let logged = logged_splits(&logs.pattern.discover(cwd, orig), name, orig);
+    let formula_str = fs::read_to_string(orig_path).unwrap();
+    let mut formula = parse_qdimacs(&formula_str, false).unwrap();
+    formula.apply_split_options(&run.options, false);
+    let jobs: Vec<Job> = split_jobs(&formula, &run.options, orig_path, &[name.to_owned()], cwd)
+        .into_iter()
+        .filter(|j| !logged.contains(&j.id))
+        .collect();
+    if jobs.is_empty() {
+        println!("All splits have logs, there is nothing left to submit.");
+        return;
+    }
+    println!("Writing jobs for {} splits without logs", jobs.len());
+    let options = ScriptOptions {
+        solver: script.solver,
+        runlim: script.runlim,
+        timeout: script.timeout,
+        memory_limit: script.memory_limit,
+        cpu_limit: script.cpu_limit,
+        partition: script.partition,
+        max_parallel: script.max_parallel,
+    };
+    if let Some(path) = script.slurm {
+        let cwd = cwd.canonicalize().unwrap();
+        let job_name = name.to_owned() + "-" + orig_path.file_name().unwrap().to_str().unwrap();
+        let slurm = match slurm_script(&jobs, &options, &job_name, &cwd) {
+            Ok(slurm) => slurm,
+            Err(e) => {
+                println!("!! {} !!", e);
+                return;
+            }
+        };
+        fs::write(&path, slurm).expect("Slurm script could not be written!");
+        println!("Wrote {:?}, submit it using sbatch", path);
+    }
+    if let Some(path) = script.parallel {
+        let parallel = match parallel_jobs(&jobs, &options) {
+            Ok(parallel) => parallel,
+            Err(e) => {
+                println!("!! {} !!", e);
+                return;
             }
+        };
+        fs::write(&path, parallel).expect("Job list could not be written!");
+        println!(
+            "Wrote {:?}, run it in {:?} using parallel < {:?}",
+            path, cwd, path
+        );
+    }
+}
+
+/// Checks the certificates of all splits against their cubes, see
+/// Formula::check_certificate, and prints the inconsistent ones.
+fn check_certificates(formula: &Formula, options: &SplitOptions, nodes: &[SplitNode]) {
+    let mut cubes: HashMap<u32, Vec<Vec<i32>>> = HashMap::new();
+    let mut checked = 0;
+    let mut inconsistent = 0;
+    for n in nodes.iter().flat_map(|n| n.nodes()) {
+        if n.result.certificate.is_empty() {
+            continue;
         }
+        let cubes = cubes.entry(n.generation).or_insert_with(|| {
+            formula.produce_splits(Formula::generation_depth(options, n.generation))
+        });
+        let cube = &cubes[n.index];
+        if !formula.certifies(n.result.result) {
+            continue;
+        }
+        checked += 1;
+        if let Err(e) = formula.check_certificate(cube, n.result.result, &n.result.certificate) {
+            inconsistent += 1;
+            println!(
+                "Split {}: inconsistent certificate of {}: {}!",
+                formula.split_id(options, n.generation, n.index),
+                n.result.name,
+                e
+            );
+        }
+    }
+    if checked > 0 {
+        println!(
+            "Certificates: {} checked, {} inconsistent",
+            checked, inconsistent
+        );
+    }
+}
+
+/// How the logs to merge are found and read.
+struct LogOptions {
+    format: LogFormat,
+    pattern: LogPattern,
+}
+
+/// What merging prints or writes besides the statistics.
+struct MergeOutput {
+    verbose: bool,
+    /// Print the decision tree of the winning strategy.
+    strategy: bool,
+    /// Write the decision tree of the winning strategy as JSON.
+    strategy_json: Option<PathBuf>,
+}
+
+fn merge(
+    orig_path: &Path,
+    name: &[String],
+    options: &SplitOptions,
+    cwd: &Path,
+    logs: &LogOptions,
+    resplit: bool,
+    output: &MergeOutput,
+) -> Option<SolveStatistics> {
+    let verbose = output.verbose;
+    let (formula, nodes) =
+        match extract_results_from_files(orig_path, name, options, cwd, logs.format, &logs.pattern)
+        {
+            Ok(results) => results,
+            Err(e) => {
+                println!("!! {} !!", e);
+                return None;
+            }
+        };
+    if resplit {
+        process_formula_resplits(
+            &formula,
+            options,
+            &nodes,
+            orig_path.to_str().unwrap(),
+            cwd,
+            verbose,
+        );
+        return None;
+    }
+    let (_rounded_depth, split_count) = formula.embedded_splits_round_fitting(options.depth as i64);
+
+    let mut orig_file_result = PathBuf::new();
+    orig_file_result.push(cwd);
+    orig_file_result
+        .push(name[0].to_owned() + "-" + orig_path.file_name().unwrap().to_str().unwrap() + ".log");
+
+    let og_formula_result: Option<SolverResult> = if orig_file_result.exists() {
+        Some(extract_result_from_file(
+            orig_file_result.as_path(),
+            &name[0],
+            orig_path.file_name().unwrap().to_str().unwrap(),
+            logs.format,
+        ))
     } else {
-        println!("!! Require either --split or (--orig and name) !!");
+        None
+    };
+
+    let statistics =
+        produce_statistics_from_run(&formula, options, &nodes, split_count, og_formula_result);
+    println!("Statistics: minimal execution path: {} , summed execution time: {} , required cores: {} , result: {}, naive split count: {} (compared to naive splits: {})",
+             statistics.minimal_execution_time_seconds,
+             statistics.summed_execution_time_seconds,
+             statistics.required_cores,
+             statistics.result,
+             statistics.naive_split_count,
+             statistics.run_tasks_compared_to_naive);
+    println!(
+        "Split results: {}",
+        count_results(
+            nodes
+                .iter()
+                .flat_map(|n| n.nodes())
+                .filter(|n| !n.probed)
+                .map(|n| &n.result)
+        )
+    );
+    for n in nodes.iter().flat_map(|n| n.nodes()) {
+        if n.result.result == SolverReturnCode::Mismatch {
+            println!(
+                "Split {}: the answer of {} contradicts its exit code!",
+                formula.split_id(options, n.generation, n.index),
+                n.result.name
+            );
+        }
+    }
+    check_certificates(&formula, options, &nodes);
+    match (
+        statistics.non_split_execution_time_seconds,
+        statistics.speedup_against_non_split,
+    ) {
+        (Some(time), Some(speedup)) => {
+            println!("Original solve time: {} gives speedup of {}", time, speedup)
+        }
+        (Some(time), None) => println!("Original solve time: {}", time),
+        (None, _) => println!(
+            "No statistics compared to non-split solving, as file {:?} not found.",
+            orig_file_result
+        ),
+    }
+    let lower = statistics.minimal_execution_time_lower_seconds;
+    if statistics.minimal_execution_time_upper_seconds != Some(lower) {
+        let upper = |u: Option<f64>| u.map_or("unbounded".to_string(), |u| u.to_string());
+        let mut bounds = format!(
+            "Time bounds: minimal execution path between {} and {}",
+            lower,
+            upper(statistics.minimal_execution_time_upper_seconds)
+        );
+        if let Some(speedup) = statistics.speedup_lower {
+            bounds += &format!(
+                " , speedup between {} and {}",
+                speedup,
+                upper(statistics.speedup_upper)
+            );
+        }
+        println!("{}", bounds);
+    }
+    if let Some(summed) = statistics.summed_cpu_seconds {
+        println!(
+            "CPU time: minimal execution path: {} , summed CPU time: {}",
+            statistics
+                .minimal_cpu_seconds
+                .map_or("unknown".to_string(), |c| c.to_string()),
+            summed
+        );
+        if let Some(non_split) = statistics.non_split_cpu_seconds {
+            println!(
+                "Original CPU time: {} , summed CPU time compared to it: {}",
+                non_split,
+                summed / non_split
+            );
+        }
+    }
+    if let (Some(kb), Some(id)) = (statistics.peak_memory_kb, &statistics.peak_memory_split) {
+        println!("Peak memory: {} KB (split {})", kb, id);
+    }
+    for h in statistics.hosts.iter() {
+        println!(
+            "Host {}: {} , wall time: {:.2} , CPU time: {:.2} , mean load: {}",
+            h.host,
+            h.results,
+            h.wall_seconds,
+            h.cpu_seconds,
+            h.load
+                .map_or("unknown".to_string(), |l| format!("{:.2}", l))
+        );
+    }
+    if verbose {
+        for n in nodes.iter().flat_map(|n| n.nodes()) {
+            let r = &n.result;
+            println!(
+                "  Split {}: {} , wall time: {} , CPU time: {} , peak memory: {} KB , host: {}",
+                formula.split_id(options, n.generation, n.index),
+                r.result,
+                r.wall_seconds,
+                r.cpu_seconds.map_or("-".to_string(), |c| c.to_string()),
+                r.peak_memory_kb.map_or("-".to_string(), |m| m.to_string()),
+                r.job.host.as_deref().unwrap_or("-")
+            );
+        }
+    }
+    if output.strategy || output.strategy_json.is_some() {
+        let Some(strategy) = strategy(&formula, options, &nodes) else {
+            println!("No strategy, as the formula is undecided.");
+            return Some(statistics);
+        };
+        if output.strategy {
+            print!("Strategy:\n{}", strategy.text());
+        }
+        if let Some(path) = &output.strategy_json {
+            fs::write(path, serde_json::to_string_pretty(&strategy).unwrap())
+                .expect("Strategy could not be written!");
+            println!("Wrote the strategy to {:?}", path);
+        }
     }
+    Some(statistics)
 }
@@ -0,0 +1,443 @@
+use crate::{Formula, JobInfo, SolverResult, SolverReturnCode, Split, SplitOptions, TimeBounds};
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantifier {
+    Forall,
+    Exists,
+}
+
+pub fn quant_from_prefix(formula: &Formula, split: &Split) -> Quantifier {
+    // Variables of DIMACS files are not in the prefix and thus
+    // existential.
+    if formula.is_universal(split.vars()[0]) {
+        Quantifier::Forall
+    } else {
+        Quantifier::Exists
+    }
+}
+
+// How much an undecided result says about why a layer is undecided.
+// Failures that need attention, e.g. missing or crashed jobs, come
+// before limits, and cancelled jobs only if there is nothing else.
+fn undecided_severity(code: SolverReturnCode) -> u8 {
+    match code {
+        SolverReturnCode::Mismatch => 7,
+        SolverReturnCode::Missing => 6,
+        SolverReturnCode::Crashed => 5,
+        SolverReturnCode::Error => 4,
+        SolverReturnCode::MemoryOut => 3,
+        SolverReturnCode::Timeout => 2,
+        SolverReturnCode::Unknown => 1,
+        SolverReturnCode::Cancelled => 0,
+        SolverReturnCode::Sat | SolverReturnCode::Unsat => 0,
+    }
+}
+
+// Reduce the result by one layer. The quantifier's player wins a layer
+// (Sat for exists, Unsat for forall) as soon as one split is won, and
+// loses it when all splits are lost. A decided layer takes the time of
+// the deciding splits, an undecided one gets the most severe undecided
+// result of its splits and its lower bound as time.
+//
+// The bounds assume that an undecided split may end either way at any
+// time after its lower bound: the layer is decided at the first split
+// won, or at the last split if all are lost.
+pub fn reduce_result(
+    quant: Quantifier,
+    single_layer_width: usize,
+    results: Vec<SolverResult>,
+) -> Vec<SolverResult> {
+    assert!(single_layer_width > 0);
+    let (win, lose) = match quant {
+        Quantifier::Exists => (SolverReturnCode::Sat, SolverReturnCode::Unsat),
+        Quantifier::Forall => (SolverReturnCode::Unsat, SolverReturnCode::Sat),
+    };
+    results
+        .chunks(single_layer_width)
+        .map(|layer| {
+            let bounds: Vec<TimeBounds> = layer.iter().map(|r| r.bounds()).collect();
+            let lowers = bounds.iter().map(|b| b.lower);
+            let uppers = bounds.iter().map(|b| b.upper.unwrap_or(f64::INFINITY));
+            // The first split that may be won, unless the last one is
+            // earlier.
+            let lower = std::iter::zip(layer, lowers.clone())
+                .filter(|(r, _)| r.result != lose)
+                .map(|(_, t)| t)
+                .fold(f64::INFINITY, f64::min)
+                .min(lowers.fold(0.0, f64::max));
+            // The first split that is won, unless the last one is
+            // earlier.
+            let upper = std::iter::zip(layer, uppers.clone())
+                .filter(|(r, _)| r.result == win)
+                .map(|(_, t)| t)
+                .fold(f64::INFINITY, f64::min)
+                .min(uppers.fold(0.0, f64::max));
+            let bounds = Some(TimeBounds {
+                lower,
+                upper: upper.is_finite().then_some(upper),
+            });
+
+            let won = layer
+                .iter()
+                .filter(|r| r.result == win)
+                .min_by(|a, b| a.wall_seconds.total_cmp(&b.wall_seconds));
+            let lost = || {
+                layer
+                    .iter()
+                    .all(|r| r.result == lose)
+                    .then(|| {
+                        layer
+                            .iter()
+                            .max_by(|a, b| a.wall_seconds.total_cmp(&b.wall_seconds))
+                    })
+                    .flatten()
+            };
+            match won.or_else(lost) {
+                Some(r) => SolverResult {
+                    bounds,
+                    ..r.clone()
+                },
+                None => SolverResult {
+                    wall_seconds: lower,
+                    result: layer
+                        .iter()
+                        .map(|r| r.result)
+                        .filter(|r| !r.is_decided())
+                        .max_by_key(|r| undecided_severity(*r))
+                        .unwrap_or(SolverReturnCode::Unknown),
+                    name: "(no solver)".to_string(),
+                    cpu_seconds: None,
+                    peak_memory_kb: None,
+                    job: JobInfo::default(),
+                    certificate: vec![],
+                    bounds,
+                },
+            }
+        })
+        .collect()
+}
+
+/// Reduces the results of all combinations of the given splits, the
+/// innermost split first. Returns all intermediate layers, the last
+/// one being the fully reduced result.
+pub fn reduce_splits(
+    formula: &Formula,
+    splits: &[Split],
+    results: Vec<SolverResult>,
+) -> Vec<Vec<SolverResult>> {
+    let mut layers = vec![results];
+    for s in splits.iter().rev() {
+        let reduced = reduce_result(
+            quant_from_prefix(formula, s),
+            s.nr_of_splits(),
+            layers.last().unwrap().clone(),
+        );
+        layers.push(reduced);
+    }
+    layers
+}
+
+/// Marks the undecided results (e.g. timeouts) that still matter,
+/// i.e. all of their parents are undecided after reducing.
+pub fn undecided_results(
+    formula: &Formula,
+    splits: &[Split],
+    results: Vec<SolverResult>,
+) -> Vec<bool> {
+    let layers = reduce_splits(formula, splits, results);
+    (0..layers[0].len())
+        .map(|i| {
+            let mut idx = i;
+            let mut undecided = !layers[0][i].result.is_decided();
+            for (s, layer) in splits.iter().rev().zip(layers[1..].iter()) {
+                idx /= s.nr_of_splits();
+                undecided &= !layer[idx].result.is_decided();
+            }
+            undecided
+        })
+        .collect()
+}
+
+/// All nodes of the trees whose result still matters for the result
+/// of the formula.
+pub fn undecided_nodes<'a>(
+    formula: &Formula,
+    options: &SplitOptions,
+    nodes: &'a [SplitNode],
+) -> Vec<&'a SplitNode> {
+    let results: Vec<SolverResult> = nodes.iter().map(|n| n.resolved(formula, options)).collect();
+    let undecided = undecided_results(formula, formula.generation_splits(options, 0), results);
+    std::iter::zip(nodes.iter(), undecided)
+        .filter(|(_, u)| *u)
+        .flat_map(|(n, _)| n.undecided_nodes(formula, options))
+        .collect()
+}
+
+impl Formula {
+    /// Depth of a generation of re-splitting. Generation 0 are the
+    /// initial splits, every further generation splits timed out
+    /// splits of the previous one deeper.
+    pub fn generation_depth(options: &SplitOptions, generation: u32) -> u32 {
+        options.depth + generation * options.resplit_depth
+    }
+    /// The depth of a run whose first generation has at least the
+    /// given number of splits, e.g. the largest id of its logs plus
+    /// one. Of the depths producing the same splits, the smallest one
+    /// is used.
+    pub fn depth_for_splits(&self, count: usize) -> Option<u32> {
+        let max = self.embedded_splits_max_depth().max(self.prefix.len()) as u32;
+        (0..=max).find(|d| self.produce_splits(*d).len() >= count)
+    }
+    /// The splits added by the given generation, outermost first.
+    pub fn generation_splits(&self, options: &SplitOptions, generation: u32) -> &[Split] {
+        let used = self.used_splits(Formula::generation_depth(options, generation));
+        if generation == 0 {
+            used
+        } else {
+            let previous = self
+                .used_splits(Formula::generation_depth(options, generation - 1))
+                .len();
+            &used[previous..]
+        }
+    }
+    /// Number of splits a single split of the previous generation is
+    /// split into by the given generation.
+    pub fn generation_width(&self, options: &SplitOptions, generation: u32) -> usize {
+        self.generation_splits(options, generation)
+            .iter()
+            .map(|s| s.nr_of_splits())
+            .product()
+    }
+    /// Whether timed out splits of the given generation can be split
+    /// deeper.
+    pub fn can_resplit(&self, options: &SplitOptions, generation: u32) -> bool {
+        options.resplit_depth > 0
+            && self
+                .generation_splits(options, generation + 1)
+                .iter()
+                .any(|s| !s.is_expanded())
+    }
+    /// Name of the split with the given index in its generation. Splits
+    /// of the first generation are just numbered, re-splits append
+    /// their index inside of their parent, e.g. "3.1".
+    pub fn split_id(&self, options: &SplitOptions, generation: u32, index: usize) -> String {
+        let mut ids: Vec<usize> = vec![];
+        let mut rest = index;
+        for g in (1..=generation).rev() {
+            let width = self.generation_width(options, g);
+            ids.push(rest % width);
+            rest /= width;
+        }
+        ids.push(rest);
+        ids.iter()
+            .rev()
+            .map(|i| i.to_string())
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+    /// The assumptions of the split with the given name.
+    pub fn split_cube(&self, options: &SplitOptions, id: &str) -> Vec<i32> {
+        let generation = id.matches('.').count() as u32;
+        let splits = self.produce_splits(Formula::generation_depth(options, generation));
+        let index = (0..splits.len())
+            .find(|i| self.split_id(options, generation, *i) == id)
+            .unwrap_or_else(|| panic!("There is no split {}!", id));
+        splits[index].clone()
+    }
+}
+
+/// The result of a split job, together with the results of its
+/// re-splits if it timed out and was split deeper.
+#[derive(Debug, Clone)]
+pub struct SplitNode {
+    pub generation: u32,
+    /// Index of the split in its generation.
+    pub index: usize,
+    pub result: SolverResult,
+    pub children: Vec<SplitNode>,
+    /// Probing decided the split, it was never run and its result has
+    /// no solver.
+    pub probed: bool,
+}
+
+impl SplitNode {
+    /// The result including the re-splits. They are run after their
+    /// parent timed out, so their time adds to the time of the parent.
+    pub fn resolved(&self, formula: &Formula, options: &SplitOptions) -> SolverResult {
+        if self.children.is_empty() || self.result.result.is_decided() {
+            return self.result.clone();
+        }
+        let results: Vec<SolverResult> = self
+            .children
+            .iter()
+            .map(|c| c.resolved(formula, options))
+            .collect();
+        let splits = formula.generation_splits(options, self.generation + 1);
+        let r = reduce_splits(formula, splits, results).pop().unwrap()[0].clone();
+        let bounds = r.bounds();
+        SolverResult {
+            wall_seconds: self.result.wall_seconds + r.wall_seconds,
+            bounds: Some(TimeBounds {
+                lower: self.result.wall_seconds + bounds.lower,
+                upper: bounds.upper.map(|u| self.result.wall_seconds + u),
+            }),
+            cpu_seconds: self
+                .result
+                .cpu_seconds
+                .zip(r.cpu_seconds)
+                .map(|(a, b)| a + b),
+            peak_memory_kb: self.result.peak_memory_kb.max(r.peak_memory_kb),
+            ..r
+        }
+    }
+
+    /// All nodes of this tree.
+    pub fn nodes(&self) -> Vec<&SplitNode> {
+        let mut nodes = vec![self];
+        for c in self.children.iter() {
+            nodes.extend(c.nodes());
+        }
+        nodes
+    }
+
+    /// Nodes of this tree that still matter for the result, given
+    /// that this node still matters.
+    pub fn undecided_nodes(&self, formula: &Formula, options: &SplitOptions) -> Vec<&SplitNode> {
+        if self.resolved(formula, options).result.is_decided() {
+            return vec![];
+        }
+        let mut nodes = vec![self];
+        if self.children.is_empty() {
+            return nodes;
+        }
+        let results: Vec<SolverResult> = self
+            .children
+            .iter()
+            .map(|c| c.resolved(formula, options))
+            .collect();
+        let splits = formula.generation_splits(options, self.generation + 1);
+        let undecided = undecided_results(formula, splits, results);
+        nodes.extend(
+            std::iter::zip(self.children.iter(), undecided)
+                .filter(|(_, u)| *u)
+                .flat_map(|(c, _)| c.undecided_nodes(formula, options)),
+        );
+        nodes
+    }
+
+    /// Undecided leaves that still matter for the result, given that
+    /// this node still matters.
+    pub fn undecided_leaves(&self, formula: &Formula, options: &SplitOptions) -> Vec<&SplitNode> {
+        self.undecided_nodes(formula, options)
+            .into_iter()
+            .filter(|n| n.children.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_qdimacs;
+
+    fn res(result: SolverReturnCode, wall_seconds: f64) -> SolverResult {
+        SolverResult {
+            wall_seconds,
+            result,
+            name: "test".to_string(),
+            cpu_seconds: None,
+            peak_memory_kb: None,
+            job: JobInfo::default(),
+            certificate: vec![],
+            bounds: None,
+        }
+    }
+
+    #[test]
+    fn test_reduce_undecided() {
+        use SolverReturnCode::*;
+        let results = vec![
+            res(Timeout, 1.0),
+            res(Missing, 0.0),
+            res(Unsat, 2.0),
+            res(Cancelled, 0.0),
+        ];
+        let exists = reduce_result(Quantifier::Exists, 2, results.clone());
+        assert_eq!(exists[0].result, Missing);
+        assert_eq!(exists[1].result, Cancelled);
+        let forall = reduce_result(Quantifier::Forall, 4, results);
+        assert_eq!(forall[0].result, Unsat);
+    }
+
+    #[test]
+    fn test_reduce_bounds() {
+        use SolverReturnCode::*;
+        let bounds = |r: &SolverResult| (r.bounds().lower, r.bounds().upper);
+        let results = vec![
+            res(Sat, 5.0),
+            res(Timeout, 3.0),
+            res(Unsat, 2.0),
+            res(Timeout, 7.0),
+        ];
+        // The timeout might have been won before the Sat split.
+        let exists = reduce_result(Quantifier::Exists, 2, results);
+        assert_eq!(exists[0].result, Sat);
+        assert_eq!(exists[0].wall_seconds, 5.0);
+        assert_eq!(bounds(&exists[0]), (3.0, Some(5.0)));
+        assert_eq!(exists[1].result, Timeout);
+        assert_eq!(bounds(&exists[1]), (7.0, None));
+
+        let forall = reduce_result(Quantifier::Forall, 2, exists);
+        assert_eq!(forall[0].result, Timeout);
+        assert_eq!(forall[0].wall_seconds, 7.0);
+        assert_eq!(bounds(&forall[0]), (7.0, None));
+    }
+
+    #[test]
+    fn test_resplit_tree() {
+        let f = parse_qdimacs("p cnf 3 1\na 1 0\ne 2 3 0\n1 2 3 0\n", false).unwrap();
+        let options = SplitOptions {
+            depth: 1,
+            resplit_depth: 1,
+            ..Default::default()
+        };
+        assert_eq!(f.split_id(&options, 0, 1), "1");
+        assert_eq!(f.split_id(&options, 2, 5), "1.0.1");
+
+        // Universal 1: the first branch timed out, the second is Sat.
+        // The timeout is re-split on 2, where 2 is Sat.
+        let child = |index: usize, result: SolverReturnCode| SplitNode {
+            generation: 1,
+            index,
+            result: res(result, 2.0),
+            children: vec![],
+            probed: false,
+        };
+        let timed_out = SplitNode {
+            generation: 0,
+            index: 0,
+            result: res(SolverReturnCode::Timeout, 10.0),
+            children: vec![
+                child(0, SolverReturnCode::Timeout),
+                child(1, SolverReturnCode::Sat),
+            ],
+            probed: false,
+        };
+        let r = timed_out.resolved(&f, &options);
+        assert_eq!(r.result, SolverReturnCode::Sat);
+        assert_eq!(r.wall_seconds, 12.0);
+        assert!(timed_out.undecided_leaves(&f, &options).is_empty());
+
+        let undecided = SplitNode {
+            children: vec![
+                child(0, SolverReturnCode::Timeout),
+                child(1, SolverReturnCode::Unsat),
+            ],
+            ..timed_out
+        };
+        let leaves = undecided.undecided_leaves(&f, &options);
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(leaves[0].index, 0);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::Formula;
+
+/// Unit propagation over the matrix of a formula, respecting the
+/// quantifier prefix through universal reduction. Plain DIMACS
+/// formulas have no prefix, so every variable is existential and this
+/// is just regular unit propagation.
+#[derive(Debug, Clone)]
+pub struct Propagator<'a> {
+    formula: &'a Formula,
+    // Quantifier level of each variable, free variables are on level 0.
+    level: Vec<usize>,
+    universal: Vec<bool>,
+    // Clauses indexed by literal, see lit_index.
+    occurrences: Vec<Vec<usize>>,
+    // Clauses with at most one existential literal. These may already
+    // be unit or empty without any assignment.
+    short_clauses: Vec<usize>,
+}
+
+fn lit_index(lit: i32) -> usize {
+    let v = lit.unsigned_abs() as usize;
+    if lit > 0 {
+        2 * v
+    } else {
+        2 * v + 1
+    }
+}
+
+impl<'a> Propagator<'a> {
+    pub fn new(formula: &'a Formula) -> Propagator<'a> {
+        let max_var = formula
+            .matrix
+            .iter()
+            .flatten()
+            .chain(formula.prefix.iter())
+            .map(|l| l.abs())
+            .max()
+            .unwrap_or(0)
+            .max(formula.nr_of_variables) as usize;
+
+        let mut level: Vec<usize> = vec![0; max_var + 1];
+        let mut universal: Vec<bool> = vec![false; max_var + 1];
+        let mut current_level = 0;
+        let mut last_q = 0;
+        for q in formula.prefix.iter() {
+            if last_q == 0 || (last_q > 0) != (*q > 0) {
+                current_level += 1;
+            }
+            level[q.unsigned_abs() as usize] = current_level;
+            universal[q.unsigned_abs() as usize] = *q > 0;
+            last_q = *q;
+        }
+
+        let mut occurrences: Vec<Vec<usize>> = vec![vec![]; 2 * max_var + 2];
+        let mut short_clauses: Vec<usize> = vec![];
+        for (i, clause) in formula.matrix.iter().enumerate() {
+            for l in clause.iter() {
+                occurrences[lit_index(*l)].push(i);
+            }
+            let existentials = clause
+                .iter()
+                .filter(|l| !universal[l.unsigned_abs() as usize])
+                .count();
+            if existentials <= 1 {
+                short_clauses.push(i);
+            }
+        }
+
+        Propagator {
+            formula,
+            level,
+            universal,
+            occurrences,
+            short_clauses,
+        }
+    }
+
+    pub fn nr_of_variables(&self) -> usize {
+        self.level.len() - 1
+    }
+
+    /// Clauses in which the given literal occurs.
+    pub fn occurrences(&self, lit: i32) -> &[usize] {
+        &self.occurrences[lit_index(lit)]
+    }
+
+    fn value(assignment: &[i8], lit: i32) -> i8 {
+        let v = assignment[lit.unsigned_abs() as usize];
+        if lit > 0 {
+            v
+        } else {
+            -v
+        }
+    }
+
+    // Checks a single clause under the current assignment. Returns
+    // Err(()) on conflict, otherwise maybe an implied literal.
+    fn check_clause(&self, assignment: &[i8], clause: &[i32]) -> Result<Option<i32>, ()> {
+        let mut unit: Option<i32> = None;
+        let mut existentials = 0;
+        for l in clause.iter() {
+            match Self::value(assignment, *l) {
+                1 => return Ok(None),
+                0 if !self.universal[l.unsigned_abs() as usize] => {
+                    existentials += 1;
+                    unit = Some(*l);
+                }
+                _ => (),
+            }
+        }
+        match (existentials, unit) {
+            (0, _) => Err(()),
+            (1, Some(e)) => {
+                // Only unit if all remaining universals can be reduced,
+                // i.e. are quantified after the existential literal.
+                let e_level = self.level[e.unsigned_abs() as usize];
+                let blocked = clause.iter().any(|l| {
+                    let v = l.unsigned_abs() as usize;
+                    self.universal[v] && Self::value(assignment, *l) == 0 && self.level[v] < e_level
+                });
+                if blocked {
+                    Ok(None)
+                } else {
+                    Ok(Some(e))
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Propagates the given assumptions. Returns all assigned literals
+    /// (the assumptions first) or None if a conflict was found.
+    pub fn propagate(&self, assumptions: &[i32]) -> Option<Vec<i32>> {
+        let mut assignment: Vec<i8> = vec![0; self.level.len()];
+        let mut trail: Vec<i32> = vec![];
+
+        let assign = |assignment: &mut Vec<i8>, trail: &mut Vec<i32>, lit: i32| -> bool {
+            match Self::value(assignment, lit) {
+                1 => true,
+                -1 => false,
+                _ => {
+                    assignment[lit.unsigned_abs() as usize] = if lit > 0 { 1 } else { -1 };
+                    trail.push(lit);
+                    true
+                }
+            }
+        };
+
+        for a in assumptions.iter() {
+            if !assign(&mut assignment, &mut trail, *a) {
+                return None;
+            }
+        }
+        for c in self.short_clauses.iter() {
+            match self.check_clause(&assignment, &self.formula.matrix[*c]) {
+                Err(()) => return None,
+                Ok(Some(l)) => {
+                    if !assign(&mut assignment, &mut trail, l) {
+                        return None;
+                    }
+                }
+                Ok(None) => (),
+            }
+        }
+
+        let mut head = 0;
+        while head < trail.len() {
+            let falsified = -trail[head];
+            head += 1;
+            for c in self.occurrences(falsified).iter() {
+                match self.check_clause(&assignment, &self.formula.matrix[*c]) {
+                    Err(()) => return None,
+                    Ok(Some(l)) => {
+                        if !assign(&mut assignment, &mut trail, l) {
+                            return None;
+                        }
+                    }
+                    Ok(None) => (),
+                }
+            }
+        }
+
+        Some(trail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_qdimacs;
+
+    #[test]
+    fn test_propagate_qbf() {
+        let f = parse_qdimacs("p cnf 3 2\na 1 0\ne 2 3 0\n1 2 0\n-2 3 0\n", false).unwrap();
+        let p = Propagator::new(&f);
+        assert_eq!(p.propagate(&[-1]), Some(vec![-1, 2, 3]));
+        assert_eq!(p.propagate(&[-1, -3]), None);
+        // The universal 1 blocks 2 from being unit.
+        assert_eq!(p.propagate(&[]), Some(vec![]));
+    }
+}
@@ -0,0 +1,941 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::logs::{parse_log, LogFormat};
+use crate::merge::undecided_nodes;
+use crate::{
+    count_results, id_list, log_path, write_qdimacs_to, Formula, JobInfo, ProbeResult,
+    ResultCollector, SolverResult, SolverReturnCode, SplitOptions,
+};
+
+/// A solver configuration.
+#[derive(Debug, Clone)]
+pub struct Solver {
+    /// Name of the configuration, used for the log files.
+    pub name: String,
+    /// Solver command line. The split file replaces "{}", or is
+    /// appended if there is none.
+    pub command: Vec<String>,
+}
+
+/// Options of the local solver runner.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Portfolio of solvers run concurrently on every split. The first
+    /// definitive answer wins.
+    pub solvers: Vec<Solver>,
+    /// Number of jobs to run in parallel, each running all solvers.
+    pub jobs: usize,
+    /// Wall clock timeout of each job in seconds.
+    pub timeout: Option<f64>,
+    /// Address space limit of each job in MB.
+    pub memory_limit: Option<u64>,
+    /// CPU time limit of each job in seconds.
+    pub cpu_limit: Option<u64>,
+    /// File the progress is saved to after every job. An existing one
+    /// is resumed from.
+    pub state: Option<PathBuf>,
+}
+
+/// The formula a job solves.
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// A written split file.
+    File(PathBuf),
+    /// A split of the shared formula at the given depth. It is only
+    /// generated when the job starts and written to the solver's stdin.
+    Split {
+        formula: Arc<Formula>,
+        depth: u32,
+        cube: Vec<i32>,
+    },
+}
+
+/// A split to solve, together with the logs to write, one per solver.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub input: Input,
+    pub logs: Vec<PathBuf>,
+}
+
+/// Finds all written splits (of all generations) of the original file
+/// in the working directory, ordered by their id. Splits with ids the
+/// given options do not produce, e.g. left over from a different depth,
+/// are skipped.
+pub fn split_jobs(
+    formula: &Formula,
+    options: &SplitOptions,
+    orig_file: &Path,
+    names: &[String],
+    cwd: &Path,
+) -> Vec<Job> {
+    lazy_static! {
+        static ref SPLIT_ID: Regex = Regex::new("^(\\d+(?:\\.\\d+)*):").unwrap();
+    }
+    let orig_name = orig_file.file_name().unwrap().to_str().unwrap();
+    let mut jobs: Vec<(Vec<usize>, Job)> = fs::read_dir(cwd)
+        .unwrap()
+        .filter_map(|entry| {
+            let path = entry.unwrap().path();
+            let filename = path.file_name()?.to_str()?.to_owned();
+            let id = SPLIT_ID.captures(&filename)?.get(1)?.as_str().to_owned();
+            if filename[id.len() + 1..] != *orig_name {
+                return None;
+            }
+            let key = id.split('.').map(|i| i.parse::<usize>().unwrap()).collect();
+            Some((
+                key,
+                Job {
+                    logs: names
+                        .iter()
+                        .map(|name| log_path(orig_file, name, &id, cwd))
+                        .collect(),
+                    id,
+                    input: Input::File(path),
+                },
+            ))
+        })
+        .collect();
+    jobs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // The ids split_id produces, checked index by index inside of the
+    // parent.
+    let first = formula.produce_splits(options.depth).len();
+    let known = |key: &[usize]| {
+        key[0] < first
+            && (1..key.len()).all(|g| {
+                formula.can_resplit(options, g as u32 - 1)
+                    && key[g] < formula.generation_width(options, g as u32)
+            })
+    };
+    let (jobs, unknown): (Vec<_>, Vec<_>) = jobs.into_iter().partition(|(key, _)| known(key));
+    if !unknown.is_empty() {
+        let ids: Vec<&str> = unknown.iter().map(|(_, j)| j.id.as_str()).collect();
+        println!(
+            "Skipping {} splits not produced by the split options: {}",
+            ids.len(),
+            id_list(&ids)
+        );
+    }
+    jobs.into_iter().map(|(_, j)| j).collect()
+}
+
+/// Jobs for all splits of the first generation, streamed to the
+/// solvers instead of written to files. Splits decided by probing are
+/// skipped, just like when writing them.
+pub fn stdin_jobs(
+    formula: Arc<Formula>,
+    options: &SplitOptions,
+    orig_file: &Path,
+    names: &[String],
+    cwd: &Path,
+) -> Vec<Job> {
+    let splits = formula.produce_splits(options.depth);
+    let probes = if options.probe {
+        formula.probe_splits(options.depth)
+    } else {
+        vec![ProbeResult::Open; splits.len()]
+    };
+    std::iter::zip(splits, probes)
+        .enumerate()
+        .filter(|(_, (_, p))| *p == ProbeResult::Open)
+        .map(|(i, (cube, _))| {
+            let id = formula.split_id(options, 0, i);
+            Job {
+                logs: names
+                    .iter()
+                    .map(|name| log_path(orig_file, name, &id, cwd))
+                    .collect(),
+                id,
+                input: Input::Split {
+                    formula: formula.clone(),
+                    depth: options.depth,
+                    cube,
+                },
+            }
+        })
+        .collect()
+}
+
+/// The state file of a run, next to the log of the original file.
+pub fn state_path(orig_file: &Path, name: &str, cwd: &Path) -> PathBuf {
+    let mut p = PathBuf::new();
+    p.push(cwd);
+    p.push(format!(
+        "{}-{}.state",
+        name,
+        orig_file.file_name().unwrap().to_str().unwrap()
+    ));
+    p
+}
+
+// Whether the runner finished writing the log, i.e. the status line
+// was written.
+fn log_complete(log: &Path) -> bool {
+    fs::read_to_string(log).is_ok_and(|s| s.lines().any(|l| l.starts_with("[runlim] status:")))
+}
+
+/// Reads the results of finished jobs from a state file. Each line is
+/// "<id> pending" or "<id> done <result> <wall> <cpu> <space> <solver>",
+/// where unknown values are "-". The solver is the winner of the
+/// portfolio.
+pub fn read_state(path: &Path) -> HashMap<String, SolverResult> {
+    let content = fs::read_to_string(path).expect("State file could not be read!");
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(1) != Some(&"done") {
+                return None;
+            }
+            let parse = || -> Option<SolverResult> {
+                if fields.len() != 7 {
+                    return None;
+                }
+                let optional = |i: usize| (fields[i] != "-").then_some(fields[i]);
+                Some(SolverResult {
+                    result: fields[2].parse().ok()?,
+                    wall_seconds: fields[3].parse().ok()?,
+                    cpu_seconds: optional(4).map(|c| c.parse()).transpose().ok()?,
+                    peak_memory_kb: optional(5).map(|m| m.parse()).transpose().ok()?,
+                    job: JobInfo::default(),
+                    certificate: vec![],
+                    bounds: None,
+                    name: fields[6].to_owned(),
+                })
+            };
+            let result = parse()
+                .unwrap_or_else(|| panic!("Invalid line in state file {:?}: {}", path, line));
+            Some((fields[0].to_owned(), result))
+        })
+        .collect()
+}
+
+fn write_state(path: &Path, jobs: &[Job], results: &[Option<SolverResult>]) {
+    let mut content = String::new();
+    for (job, result) in std::iter::zip(jobs, results) {
+        match result {
+            None => content.push_str(&format!("{} pending\n", job.id)),
+            Some(r) => content.push_str(&format!(
+                "{} done {} {:.2} {} {} {}\n",
+                job.id,
+                r.result,
+                r.wall_seconds,
+                r.cpu_seconds
+                    .map_or("-".to_string(), |c| format!("{:.2}", c)),
+                r.peak_memory_kb.map_or("-".to_string(), |m| m.to_string()),
+                r.name
+            )),
+        }
+    }
+    // Replaced atomically, so that a crash never leaves half a file.
+    let tmp = path.with_extension("state.tmp");
+    fs::write(&tmp, content).expect("State file could not be written!");
+    fs::rename(&tmp, path).expect("State file could not be written!");
+}
+
+/// Results of the jobs finished by an earlier, interrupted run. Jobs
+/// are only skipped if the state file has their result and all their
+/// logs are complete, everything else is run again.
+pub fn resume_jobs(jobs: &[Job], options: &RunOptions) -> Vec<Option<SolverResult>> {
+    let state = match &options.state {
+        Some(path) if path.exists() => read_state(path),
+        _ => HashMap::new(),
+    };
+    jobs.iter()
+        .map(|j| {
+            state
+                .get(&j.id)
+                .filter(|_| j.logs.iter().all(|l| log_complete(l)))
+                .cloned()
+        })
+        .collect()
+}
+
+// Without a file, the formula is read from stdin and only "{}" is
+// replaced by /dev/stdin, for solvers requiring a path.
+fn solver_command(solver: &[String], input: Option<&Path>) -> Command {
+    let path = input.map_or("/dev/stdin", |p| p.to_str().unwrap());
+    let mut args: Vec<String> = solver.iter().map(|a| a.replace("{}", path)).collect();
+    if input.is_some() && !solver.iter().any(|a| a.contains("{}")) {
+        args.push(path.to_owned());
+    }
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..]);
+    // Own process group, so that killing also reaches processes
+    // started by the solver, e.g. by wrapper scripts.
+    command.process_group(0);
+    command
+}
+
+// Exit of a solver process together with its resource usage.
+struct Exit {
+    code: Option<i32>,
+    signal: Option<i32>,
+    usage: libc::rusage,
+}
+
+impl Exit {
+    fn cpu_seconds(&self) -> f64 {
+        let seconds = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+        seconds(self.usage.ru_utime) + seconds(self.usage.ru_stime)
+    }
+
+    fn peak_memory_kb(&self) -> u64 {
+        // Linux reports the maximum resident set size in KB.
+        self.usage.ru_maxrss as u64
+    }
+}
+
+// Waits for the solver process and collects its resource usage. Returns
+// None if it is still running and block is not set.
+fn wait(pid: libc::pid_t, block: bool) -> Option<Exit> {
+    let mut status: libc::c_int = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let flags = if block { 0 } else { libc::WNOHANG };
+    let r = unsafe { libc::wait4(pid, &mut status, flags, &mut usage) };
+    if r == 0 {
+        return None;
+    }
+    if r < 0 {
+        panic!(
+            "Waiting for solver failed: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    Some(Exit {
+        code: libc::WIFEXITED(status).then(|| libc::WEXITSTATUS(status)),
+        signal: libc::WIFSIGNALED(status).then(|| libc::WTERMSIG(status)),
+        usage,
+    })
+}
+
+fn kill(pid: libc::pid_t) -> Exit {
+    unsafe {
+        libc::kill(-pid, libc::SIGKILL);
+    }
+    wait(pid, true).unwrap()
+}
+
+fn rlimit(soft: u64, hard: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    }
+}
+
+fn hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } != 0 {
+        return None;
+    }
+    let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    Some(String::from_utf8_lossy(&name[..end]).into_owned())
+}
+
+// Information about a job run on this machine.
+fn job_info(job: &Job, solver: &Solver, status: &str) -> JobInfo {
+    JobInfo {
+        status: Some(status.to_owned()),
+        host: hostname(),
+        run_name: Some(solver.name.to_owned()),
+        task: Some(job.id.to_owned()),
+        args: Some(solver.command.join(" ")),
+        ..Default::default()
+    }
+}
+
+fn write_log_header(log: &mut File, job: &Job, solver: &Solver) {
+    writeln!(log, "c qdimacs_splitter run: name:  {}", solver.name).unwrap();
+    writeln!(log, "c qdimacs_splitter run: task:  {}", job.id).unwrap();
+    writeln!(
+        log,
+        "c qdimacs_splitter run: args:  {}",
+        solver.command.join(" ")
+    )
+    .unwrap();
+    if let Some(host) = hostname() {
+        writeln!(log, "[runlim] host:\t\t\t{}", host).unwrap();
+    }
+    log.flush().unwrap();
+}
+
+/// Writes the logs of a job that was cancelled before it started.
+fn write_cancelled_logs(job: &Job, options: &RunOptions) -> SolverResult {
+    for (solver, path) in std::iter::zip(&options.solvers, &job.logs) {
+        let mut log = File::create(path).expect("Log file could not be created!");
+        write_log_header(&mut log, job, solver);
+        writeln!(log, "[runlim] real:\t\t\t0.00 seconds").unwrap();
+        writeln!(log, "[runlim] status:\t\tcancelled").unwrap();
+    }
+    SolverResult {
+        wall_seconds: 0.0,
+        result: SolverReturnCode::Cancelled,
+        name: options.solvers[0].name.to_owned(),
+        cpu_seconds: None,
+        peak_memory_kb: None,
+        job: job_info(job, &options.solvers[0], "cancelled"),
+        certificate: vec![],
+        bounds: None,
+    }
+}
+
+// Whether the solver output reports a failed allocation. Exceeding the
+// address space limit makes allocations fail, which solvers report in
+// all kinds of ways, or not at all if the peak memory tells.
+fn allocation_failed(log: &Path) -> bool {
+    lazy_static! {
+        static ref ALLOCATION: Regex = Regex::new(
+            "(?i)out of memory|bad_alloc|MemoryError|cannot allocate memory|memory allocation .*failed"
+        )
+        .unwrap();
+    }
+    fs::read(log).is_ok_and(|output| ALLOCATION.is_match(&String::from_utf8_lossy(&output)))
+}
+
+// Why a solver was killed.
+#[derive(Clone, Copy, PartialEq)]
+enum Kill {
+    Cancelled,
+    Timeout,
+    // Another solver of the portfolio answered first.
+    Lost,
+}
+
+// A solver process of a job, together with its log.
+struct Process {
+    pid: libc::pid_t,
+    log: File,
+    path: PathBuf,
+    exit: Option<(Exit, f64)>,
+    killed: Option<Kill>,
+    // Whether it exited with an answer agreeing with its exit code.
+    decided: bool,
+}
+
+impl Process {
+    fn start(
+        job: &Job,
+        split: Option<&Arc<Formula>>,
+        solver: &Solver,
+        path: &Path,
+        options: &RunOptions,
+    ) -> Process {
+        let mut log = File::create(path).expect("Log file could not be created!");
+        write_log_header(&mut log, job, solver);
+
+        let file = match &job.input {
+            Input::File(path) => Some(path.as_path()),
+            Input::Split { .. } => None,
+        };
+        let mut command = solver_command(&solver.command, file);
+        let memory_limit = options.memory_limit;
+        let cpu_limit = options.cpu_limit;
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(mb) = memory_limit {
+                    let bytes = mb * 1024 * 1024;
+                    if libc::setrlimit(libc::RLIMIT_AS, &rlimit(bytes, bytes)) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                if let Some(seconds) = cpu_limit {
+                    // SIGXCPU at the soft limit, SIGKILL a second later.
+                    if libc::setrlimit(libc::RLIMIT_CPU, &rlimit(seconds, seconds + 1)) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        // Reaped using wait4, which also reports resource usage.
+        #[allow(clippy::zombie_processes)]
+        let mut child = command
+            .stdin(if split.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(log.try_clone().unwrap())
+            .stderr(log.try_clone().unwrap())
+            .spawn()
+            .unwrap_or_else(|e| panic!("Could not start solver {:?}: {}", solver.command, e));
+        if let Some(split) = split {
+            let split = split.clone();
+            let stdin = child.stdin.take().unwrap();
+            // Written concurrently, as the solver may start solving
+            // before reading everything. Writing fails once the solver
+            // exits or is killed, which is fine.
+            std::thread::spawn(move || {
+                let mut stdin = std::io::BufWriter::new(stdin);
+                let _ = write_qdimacs_to(&mut stdin, &split).and_then(|_| stdin.flush());
+            });
+        }
+        Process {
+            pid: child.id() as libc::pid_t,
+            log,
+            path: path.to_owned(),
+            exit: None,
+            killed: None,
+            decided: false,
+        }
+    }
+
+    // Records the exit of the solver. It decides the job like merging
+    // would read its log, i.e. an answer followed by exit 0 counts, but
+    // a solver contradicting itself cannot end the race.
+    fn exited(&mut self, exit: Exit, wall_seconds: f64) {
+        let output = fs::read(&self.path).unwrap_or_default();
+        let mut info = parse_log(&String::from_utf8_lossy(&output), "", LogFormat::Solver);
+        info.exit_code = exit.code;
+        info.status = Some(match exit.signal {
+            Some(signal) => format!("signal({})", signal),
+            None => "ok".to_string(),
+        });
+        self.decided = info.result().is_decided();
+        self.exit = Some((exit, wall_seconds));
+    }
+
+    fn kill(&mut self, reason: Kill, start: Instant) {
+        if self.exit.is_none() {
+            self.exit = Some((kill(self.pid), start.elapsed().as_secs_f64()));
+            self.killed = Some(reason);
+        }
+    }
+
+    // Writes the runlim lines to the log and returns the result, read
+    // back from the log like merging does, so the answer of the solver
+    // is checked against its exit code.
+    fn finish(mut self, job: &Job, solver: &Solver, options: &RunOptions) -> SolverResult {
+        let (exit, wall_seconds) = self.exit.unwrap();
+        let cpu_seconds = exit.cpu_seconds();
+        let peak_memory_kb = exit.peak_memory_kb();
+
+        let solved = matches!(exit.code, Some(10) | Some(20));
+        let failed = exit.signal.is_some() || exit.code.is_some_and(|c| c != 0 && !solved);
+        let out_of_cpu = options.cpu_limit.is_some_and(|l| {
+            exit.signal == Some(libc::SIGXCPU)
+                || (exit.signal == Some(libc::SIGKILL) && cpu_seconds >= l as f64)
+        });
+        let out_of_memory = options.memory_limit.is_some_and(|mb| {
+            failed && (peak_memory_kb >= mb * 1024 * 9 / 10 || allocation_failed(&self.path))
+        });
+        let cancelled = matches!(self.killed, Some(Kill::Cancelled) | Some(Kill::Lost));
+        let status = if cancelled {
+            "cancelled".to_string()
+        } else if solved {
+            "ok".to_string()
+        } else if self.killed == Some(Kill::Timeout) || out_of_cpu {
+            "out of time".to_string()
+        } else if out_of_memory {
+            "out of memory".to_string()
+        } else if let Some(signal) = exit.signal {
+            format!("signal({})", signal)
+        } else {
+            "ok".to_string()
+        };
+
+        let log = &mut self.log;
+        if let Some(code) = exit.code.filter(|c| *c != 0) {
+            writeln!(log, "Command exited with non-zero status {}", code).unwrap();
+        }
+        writeln!(log, "[runlim] real:\t\t\t{:.2} seconds", wall_seconds).unwrap();
+        writeln!(log, "[runlim] time:\t\t\t{:.2} seconds", cpu_seconds).unwrap();
+        writeln!(log, "[runlim] space:\t\t\t{} KB", peak_memory_kb).unwrap();
+        writeln!(log, "[runlim] status:\t\t{}", status).unwrap();
+        if let Some(code) = exit.code {
+            writeln!(log, "[runlim] result:\t\t{}", code).unwrap();
+        }
+
+        let output = fs::read(&self.path).expect("Log file could not be read!");
+        let info = parse_log(
+            &String::from_utf8_lossy(&output),
+            &job.id,
+            LogFormat::Runlim,
+        );
+        SolverResult {
+            wall_seconds,
+            result: info.result(),
+            name: solver.name.to_owned(),
+            cpu_seconds: Some(cpu_seconds),
+            peak_memory_kb: Some(peak_memory_kb),
+            job: job_info(job, solver, &status),
+            certificate: info.certificate,
+            bounds: None,
+        }
+    }
+}
+
+/// Runs all solvers concurrently on a single split and writes logs
+/// compatible with runlim, so that merging can read them. The first
+/// definitive answer wins and the other solvers are killed, as are all
+/// solvers as soon as cancel is set. Memory and CPU time of each solver
+/// are limited using rlimits.
+///
+/// The result is the one of the winner, or of the first solver if none
+/// answered. The CPU time is the one of all solvers together, as they
+/// shared the job.
+pub fn run_job(job: &Job, options: &RunOptions, cancel: &AtomicBool) -> SolverResult {
+    if cancel.load(Ordering::Relaxed) {
+        return write_cancelled_logs(job, options);
+    }
+    let start = Instant::now();
+    // Generated once for all solvers of the portfolio.
+    let split = match &job.input {
+        Input::File(_) => None,
+        Input::Split {
+            formula,
+            depth,
+            cube,
+        } => Some(Arc::new(formula.split_formula(*depth, cube))),
+    };
+    let mut processes: Vec<Process> = std::iter::zip(&options.solvers, &job.logs)
+        .map(|(solver, log)| Process::start(job, split.as_ref(), solver, log, options))
+        .collect();
+
+    let timeout = options.timeout.map(Duration::from_secs_f64);
+    let kill_all = |processes: &mut Vec<Process>, reason: Kill| {
+        for p in processes.iter_mut() {
+            p.kill(reason, start);
+        }
+    };
+    loop {
+        for p in processes.iter_mut().filter(|p| p.exit.is_none()) {
+            if let Some(exit) = wait(p.pid, false) {
+                p.exited(exit, start.elapsed().as_secs_f64());
+            }
+        }
+        if processes.iter().any(|p| p.decided) {
+            kill_all(&mut processes, Kill::Lost);
+            break;
+        }
+        if processes.iter().all(|p| p.exit.is_some()) {
+            break;
+        }
+        if cancel.load(Ordering::Relaxed) {
+            kill_all(&mut processes, Kill::Cancelled);
+            break;
+        }
+        if timeout.is_some_and(|t| start.elapsed() >= t) {
+            kill_all(&mut processes, Kill::Timeout);
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let results: Vec<SolverResult> = std::iter::zip(processes, &options.solvers)
+        .map(|(p, solver)| p.finish(job, solver, options))
+        .collect();
+    // Solvers exiting at about the same time may both have answered.
+    let winner = results
+        .iter()
+        .filter(|r| r.result.is_decided())
+        .min_by(|a, b| a.wall_seconds.total_cmp(&b.wall_seconds))
+        .unwrap_or(&results[0]);
+    SolverResult {
+        cpu_seconds: results.iter().map(|r| r.cpu_seconds).sum(),
+        peak_memory_kb: results.iter().filter_map(|r| r.peak_memory_kb).max(),
+        ..winner.clone()
+    }
+}
+
+/// Decides which jobs still matter for the result, given the results
+/// of the finished jobs. A job does not matter anymore if the results
+/// of its siblings already decide its parent, see
+/// merge::undecided_nodes.
+pub fn relevant_jobs(
+    collector: &mut ResultCollector,
+    formula: &Formula,
+    options: &SplitOptions,
+    jobs: &[Job],
+    results: &[Option<SolverResult>],
+) -> Vec<bool> {
+    let by_id: HashMap<&str, usize> = jobs
+        .iter()
+        .enumerate()
+        .map(|(i, j)| (j.id.as_str(), i))
+        .collect();
+    let nodes = collector.collect(&|id| by_id.contains_key(id), &|id| {
+        by_id
+            .get(id)
+            .and_then(|i| results[*i].clone())
+            // Running and waiting jobs have no answer yet.
+            .unwrap_or_else(|| SolverResult::missing(""))
+    });
+    let undecided: HashSet<String> = undecided_nodes(formula, options, &nodes)
+        .iter()
+        .map(|n| formula.split_id(options, n.generation, n.index))
+        .collect();
+    jobs.iter().map(|j| undecided.contains(&j.id)).collect()
+}
+
+/// Runs all jobs using the given number of parallel workers. The
+/// results are in the order of the jobs. After each answer of a job,
+/// running and waiting jobs that are no longer relevant are cancelled.
+/// Jobs finished by an earlier run are skipped, see resume_jobs.
+pub fn run_jobs(
+    jobs: &[Job],
+    options: &RunOptions,
+    relevant: &mut (dyn FnMut(&[Option<SolverResult>]) -> Vec<bool> + Send),
+) -> Vec<SolverResult> {
+    let resumed = resume_jobs(jobs, options);
+    let skipped = resumed.iter().filter(|r| r.is_some()).count();
+    if skipped > 0 {
+        println!("Resuming, skipping {} finished splits", skipped);
+    }
+    let cancel: Vec<AtomicBool> = jobs.iter().map(|_| AtomicBool::new(false)).collect();
+    if skipped > 0 {
+        for (j, rel) in relevant(&resumed).into_iter().enumerate() {
+            if !rel && resumed[j].is_none() {
+                cancel[j].store(true, Ordering::Relaxed);
+            }
+        }
+    }
+    if let Some(state) = &options.state {
+        write_state(state, jobs, &resumed);
+    }
+    let queue: Mutex<VecDeque<usize>> =
+        Mutex::new((0..jobs.len()).filter(|i| resumed[*i].is_none()).collect());
+    let results: Mutex<Vec<Option<SolverResult>>> = Mutex::new(resumed);
+    let relevant = Mutex::new(relevant);
+    let done: Mutex<usize> = Mutex::new(skipped);
+
+    std::thread::scope(|s| {
+        for _ in 0..options.jobs.max(1) {
+            s.spawn(|| loop {
+                let next = queue.lock().unwrap().pop_front();
+                let Some(i) = next else {
+                    break;
+                };
+                let r = run_job(&jobs[i], options, &cancel[i]);
+                let mut done = done.lock().unwrap();
+                *done += 1;
+                let winner = if options.solvers.len() > 1 && r.result.is_decided() {
+                    format!(" by {}", r.name)
+                } else {
+                    String::new()
+                };
+                println!(
+                    "  [{}/{}] {} {}{} in {:.2}s ({:.2}s CPU, {} KB)",
+                    done,
+                    jobs.len(),
+                    jobs[i].id,
+                    r.result,
+                    winner,
+                    r.wall_seconds,
+                    r.cpu_seconds.unwrap_or(0.0),
+                    r.peak_memory_kb.unwrap_or(0)
+                );
+                drop(done);
+                // Only an answer can decide a parent and make other jobs
+                // irrelevant.
+                let decided = r.result.is_decided();
+                let snapshot = {
+                    let mut results = results.lock().unwrap();
+                    results[i] = Some(r);
+                    if let Some(state) = &options.state {
+                        write_state(state, jobs, &results);
+                    }
+                    decided.then(|| results.clone())
+                };
+                // Outside of the lock of the results, as this rebuilds
+                // the whole tree. Cancelling with an older snapshot than
+                // another worker only cancels less.
+                if let Some(snapshot) = snapshot {
+                    let still_relevant = (relevant.lock().unwrap())(&snapshot);
+                    for (j, rel) in still_relevant.into_iter().enumerate() {
+                        if !rel && snapshot[j].is_none() {
+                            cancel[j].store(true, Ordering::Relaxed);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let results: Vec<SolverResult> = results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.unwrap())
+        .collect();
+    println!(
+        "Finished {} splits: {}",
+        results.len(),
+        count_results(results.iter())
+    );
+    if options.solvers.len() > 1 {
+        for solver in options.solvers.iter() {
+            let won = results
+                .iter()
+                .filter(|r| r.result.is_decided() && r.name == solver.name)
+                .count();
+            println!("  {} answered {} splits first", solver.name, won);
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solver_command() {
+        let args = |solver: &[&str], input: Option<&str>| -> Vec<String> {
+            let solver: Vec<String> = solver.iter().map(|s| s.to_string()).collect();
+            solver_command(&solver, input.map(Path::new))
+                .get_args()
+                .map(|a| a.to_str().unwrap().to_owned())
+                .collect()
+        };
+        let file = Some("0:f.qdimacs");
+        assert_eq!(args(&["kissat", "-q"], file), vec!["-q", "0:f.qdimacs"]);
+        assert_eq!(
+            args(&["caqe", "--in={}", "--qdo"], file),
+            vec!["--in=0:f.qdimacs", "--qdo"]
+        );
+        assert_eq!(args(&["kissat", "-q"], None), vec!["-q"]);
+        assert_eq!(args(&["caqe", "{}"], None), vec!["/dev/stdin"]);
+    }
+
+    #[test]
+    fn test_state_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("state-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let jobs: Vec<Job> = ["0", "1", "1.0"]
+            .iter()
+            .map(|id| Job {
+                id: id.to_string(),
+                input: Input::File(dir.join(format!("{}:f.qdimacs", id))),
+                logs: vec![dir.join(format!("s-{}:f.qdimacs.log", id))],
+            })
+            .collect();
+        let results = vec![
+            Some(SolverResult {
+                wall_seconds: 1.5,
+                result: SolverReturnCode::Unsat,
+                name: "s".to_string(),
+                cpu_seconds: Some(1.25),
+                peak_memory_kb: Some(2048),
+                job: JobInfo::default(),
+                certificate: vec![],
+                bounds: None,
+            }),
+            None,
+            Some(SolverResult {
+                wall_seconds: 0.0,
+                result: SolverReturnCode::Cancelled,
+                name: "s".to_string(),
+                cpu_seconds: None,
+                peak_memory_kb: None,
+                job: JobInfo::default(),
+                certificate: vec![],
+                bounds: None,
+            }),
+        ];
+        let path = dir.join("s-f.qdimacs.state");
+        write_state(&path, &jobs, &results);
+        let state = read_state(&path);
+        assert_eq!(state.len(), 2);
+        assert_eq!(state["0"].result, SolverReturnCode::Unsat);
+        assert_eq!(state["0"].cpu_seconds, Some(1.25));
+        assert_eq!(state["0"].peak_memory_kb, Some(2048));
+        assert_eq!(state["1.0"].peak_memory_kb, None);
+
+        // Only jobs with complete logs are skipped.
+        fs::write(&jobs[0].logs[0], "[runlim] status:\t\tok\n").unwrap();
+        fs::write(&jobs[2].logs[0], "c qdimacs_splitter run: name:  s\n").unwrap();
+        let options = RunOptions {
+            solvers: vec![Solver {
+                name: "s".to_string(),
+                command: vec![],
+            }],
+            jobs: 1,
+            timeout: None,
+            memory_limit: None,
+            cpu_limit: None,
+            state: Some(path),
+        };
+        let resumed = resume_jobs(&jobs, &options);
+        assert!(resumed[0].is_some());
+        assert!(resumed[1].is_none());
+        assert!(resumed[2].is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_jobs_skip_unknown_ids() {
+        let dir = std::env::temp_dir().join(format!("jobs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for id in ["0", "1", "2", "0.1", "10"] {
+            fs::write(dir.join(format!("{}:f.qdimacs", id)), "").unwrap();
+        }
+        let mut formula = crate::parse_qdimacs("p cnf 3 1\ne 1 2 3 0\n1 2 3 0\n", false).unwrap();
+        let options = SplitOptions {
+            depth: 1,
+            ..Default::default()
+        };
+        formula.apply_split_options(&options, false);
+        let ids = |options: &SplitOptions| -> Vec<String> {
+            split_jobs(&formula, options, Path::new("f.qdimacs"), &[], &dir)
+                .into_iter()
+                .map(|j| j.id)
+                .collect()
+        };
+        assert_eq!(ids(&options), vec!["0", "1"]);
+        let options = SplitOptions {
+            resplit_depth: 1,
+            ..options
+        };
+        assert_eq!(ids(&options), vec!["0", "0.1", "1"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_answer_with_exit_zero_wins() {
+        let dir = std::env::temp_dir().join(format!("race-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("0:f.cnf");
+        fs::write(&input, "p cnf 1 1\n1 0\n").unwrap();
+        let solver = |name: &str, script: &str| Solver {
+            name: name.to_string(),
+            command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+        };
+        let options = RunOptions {
+            solvers: vec![solver("slow", "sleep 30"), solver("zero", "echo 's cnf 1'")],
+            jobs: 1,
+            timeout: None,
+            memory_limit: None,
+            cpu_limit: None,
+            state: None,
+        };
+        let job = Job {
+            id: "0".to_string(),
+            input: Input::File(input),
+            logs: options
+                .solvers
+                .iter()
+                .map(|s| dir.join(format!("{}-0:f.cnf.log", s.name)))
+                .collect(),
+        };
+        let result = run_job(&job, &options, &AtomicBool::new(false));
+        assert_eq!(result.result, SolverReturnCode::Sat);
+        assert_eq!(result.name, "zero");
+        assert!(result.wall_seconds < 10.0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
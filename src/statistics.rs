@@ -0,0 +1,353 @@
+use serde::Serialize;
+
+use crate::merge::{reduce_splits, SplitNode};
+use crate::{count_results, Formula, SolverResult, SolverReturnCode, SplitOptions};
+
+/// Statistics of merging the splits of a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct SolveStatistics {
+    /// Time of the splits deciding the result, the lower bound if the
+    /// result is undecided.
+    pub minimal_execution_time_seconds: f64,
+    /// Bounds on the time of the splits deciding the result, given the
+    /// timed out splits, see SolverResult::bounds.
+    pub minimal_execution_time_lower_seconds: f64,
+    pub minimal_execution_time_upper_seconds: Option<f64>,
+    pub summed_execution_time_seconds: f64,
+    /// Wall clock time of solving the original file, if it has a log.
+    pub non_split_execution_time_seconds: Option<f64>,
+    /// Speedup if both the splits and the original file are decided.
+    pub speedup_against_non_split: Option<f64>,
+    /// Bounds on the speedup, from the bounds of both times. The upper
+    /// bound is None if unbounded.
+    pub speedup_lower: Option<f64>,
+    pub speedup_upper: Option<f64>,
+    pub required_cores: i32,
+    pub result: SolverReturnCode,
+    pub naive_split_count: i32,
+    pub run_tasks_compared_to_naive: f64,
+    /// CPU time of the splits deciding the result, if known.
+    pub minimal_cpu_seconds: Option<f64>,
+    /// CPU time of all splits with a known CPU time.
+    pub summed_cpu_seconds: Option<f64>,
+    pub non_split_cpu_seconds: Option<f64>,
+    /// Largest peak memory of all splits.
+    pub peak_memory_kb: Option<u64>,
+    /// The split with the largest peak memory.
+    pub peak_memory_split: Option<String>,
+    pub hosts: Vec<HostStatistics>,
+    pub splits: Vec<SplitStatistics>,
+}
+
+/// Summary of the splits run on a single host, to spot noisy nodes.
+#[derive(Debug, Clone, Serialize)]
+pub struct HostStatistics {
+    pub host: String,
+    pub results: String,
+    pub wall_seconds: f64,
+    pub cpu_seconds: f64,
+    /// Mean load while running.
+    pub load: Option<f64>,
+}
+
+/// The result of a single split job.
+#[derive(Debug, Clone, Serialize)]
+pub struct SplitStatistics {
+    pub id: String,
+    pub generation: u32,
+    /// Empty if probing decided the split.
+    pub solver: String,
+    pub probed: bool,
+    pub result: SolverReturnCode,
+    pub wall_seconds: f64,
+    pub cpu_seconds: Option<f64>,
+    pub peak_memory_kb: Option<u64>,
+    pub host: Option<String>,
+}
+
+/// The statistics of a run together with what was run, one row of the
+/// JSON and CSV output.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStatistics {
+    pub instance: String,
+    pub depth: u32,
+    pub solvers: Vec<String>,
+    #[serde(flatten)]
+    pub statistics: SolveStatistics,
+}
+
+fn host_statistics(jobs: &[&SplitNode]) -> Vec<HostStatistics> {
+    let mut hosts: Vec<&str> = jobs
+        .iter()
+        .filter_map(|n| n.result.job.host.as_deref())
+        .collect();
+    hosts.sort_unstable();
+    hosts.dedup();
+    hosts
+        .into_iter()
+        .map(|host| {
+            let results: Vec<&SolverResult> = jobs
+                .iter()
+                .map(|n| &n.result)
+                .filter(|r| r.job.host.as_deref() == Some(host))
+                .collect();
+            let loads: Vec<f64> = results.iter().filter_map(|r| r.job.load).collect();
+            HostStatistics {
+                host: host.to_owned(),
+                results: count_results(results.iter().copied()),
+                wall_seconds: results.iter().map(|r| r.wall_seconds).sum(),
+                cpu_seconds: results.iter().filter_map(|r| r.cpu_seconds).sum(),
+                load: (!loads.is_empty()).then(|| loads.iter().sum::<f64>() / loads.len() as f64),
+            }
+        })
+        .collect()
+}
+
+pub fn produce_statistics_from_run(
+    formula: &Formula,
+    options: &SplitOptions,
+    nodes: &[SplitNode],
+    split_count: u64,
+    og_formula_result: Option<SolverResult>,
+) -> SolveStatistics {
+    let splits = &formula.splits[0..split_count as usize];
+
+    // The splits at this point contain all results in full detail.
+    // Each element in the vector maps to some problem instance that
+    // was split from the original formula.
+
+    let splits_depth: usize = splits.iter().map(|x| x.vars().len()).sum();
+
+    let base: i32 = 2;
+    let naive_split_count = base.pow(splits_depth as u32);
+
+    // Splits decided by probing were never run.
+    let jobs: Vec<&SplitNode> = nodes
+        .iter()
+        .flat_map(|n| n.nodes())
+        .filter(|n| !n.probed)
+        .collect();
+
+    // Generations run one after another, so the largest one decides
+    // the number of cores.
+    let required_cores = (0..=jobs.iter().map(|n| n.generation).max().unwrap_or(0))
+        .map(|g| jobs.iter().filter(|n| n.generation == g).count())
+        .max()
+        .unwrap_or(0) as i32;
+
+    let summed_execution_time_seconds: f64 = jobs.iter().map(|n| n.result.wall_seconds).sum();
+
+    // This is not influenced by the \eta reordering, as the reorder
+    // happens only locally to each quantifier block and the
+    // quantifier block order doesn't change. A clause split is on
+    // existential variables, so its branches are combined using OR.
+    let results: Vec<SolverResult> = nodes.iter().map(|n| n.resolved(formula, options)).collect();
+    let solver_results = reduce_splits(formula, splits, results).pop().unwrap();
+
+    assert!(solver_results.len() == 1);
+
+    let minimal_execution_time_seconds: f64 = solver_results[0].wall_seconds;
+
+    let cpu_times: Vec<f64> = jobs.iter().filter_map(|n| n.result.cpu_seconds).collect();
+    let summed_cpu_seconds = (!cpu_times.is_empty()).then(|| cpu_times.iter().sum());
+    let peak_memory = jobs
+        .iter()
+        .filter_map(|n| {
+            let id = formula.split_id(options, n.generation, n.index);
+            n.result.peak_memory_kb.map(|m| (id, m))
+        })
+        .max_by_key(|(_, m)| *m);
+
+    let bounds = solver_results[0].bounds();
+    let non_split_execution_time_seconds = og_formula_result.as_ref().map(|r| r.wall_seconds);
+    let speedup_against_non_split = non_split_execution_time_seconds
+        .filter(|_| solver_results[0].result.is_decided())
+        .map(|t| t / minimal_execution_time_seconds);
+    let og_bounds = og_formula_result.as_ref().map(|r| r.bounds());
+    let speedup_lower = og_bounds.map(|og| bounds.upper.map_or(0.0, |u| og.lower / u));
+    let speedup_upper = og_bounds
+        .and_then(|og| og.upper)
+        .filter(|_| bounds.lower > 0.0)
+        .map(|u| u / bounds.lower);
+    let non_split_cpu_seconds = og_formula_result.as_ref().and_then(|r| r.cpu_seconds);
+
+    let splits = nodes
+        .iter()
+        .flat_map(|n| n.nodes())
+        .map(|n| SplitStatistics {
+            id: formula.split_id(options, n.generation, n.index),
+            generation: n.generation,
+            solver: n.result.name.clone(),
+            probed: n.probed,
+            result: n.result.result,
+            wall_seconds: n.result.wall_seconds,
+            cpu_seconds: n.result.cpu_seconds,
+            peak_memory_kb: n.result.peak_memory_kb,
+            host: n.result.job.host.clone(),
+        })
+        .collect();
+
+    SolveStatistics {
+        minimal_execution_time_seconds,
+        minimal_execution_time_lower_seconds: bounds.lower,
+        minimal_execution_time_upper_seconds: bounds.upper,
+        summed_execution_time_seconds,
+        required_cores,
+        result: solver_results[0].result,
+        non_split_execution_time_seconds,
+        speedup_against_non_split,
+        speedup_lower,
+        speedup_upper,
+        naive_split_count,
+        run_tasks_compared_to_naive: required_cores as f64 / naive_split_count as f64,
+        minimal_cpu_seconds: solver_results[0].cpu_seconds,
+        summed_cpu_seconds,
+        non_split_cpu_seconds,
+        peak_memory_kb: peak_memory.as_ref().map(|(_, m)| *m),
+        peak_memory_split: peak_memory.map(|(id, _)| id),
+        hosts: host_statistics(&jobs),
+        splits,
+    }
+}
+
+fn csv_table(rows: &[serde_json::Map<String, serde_json::Value>]) -> String {
+    let Some(first) = rows.first() else {
+        return String::new();
+    };
+    let header: Vec<&String> = first.keys().collect();
+    let cell = |v: &serde_json::Value| {
+        let text = match v {
+            serde_json::Value::Null => String::new(),
+            serde_json::Value::String(s) => s.to_owned(),
+            v => v.to_string(),
+        };
+        if text.contains([',', '"', '\n']) {
+            format!("\"{}\"", text.replace('"', "\"\""))
+        } else {
+            text
+        }
+    };
+    let mut csv = header
+        .iter()
+        .map(|h| h.as_str())
+        .collect::<Vec<&str>>()
+        .join(",");
+    csv.push('\n');
+    for row in rows.iter() {
+        let cells: Vec<String> = header.iter().map(|h| cell(&row[h.as_str()])).collect();
+        csv.push_str(&cells.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+fn object<T: Serialize>(value: &T) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(value).unwrap() {
+        serde_json::Value::Object(row) => row,
+        _ => unreachable!(),
+    }
+}
+
+/// The key of a run in both CSVs, with the solvers comma separated.
+fn run_key(run: &RunStatistics) -> serde_json::Map<String, serde_json::Value> {
+    let mut row = serde_json::Map::new();
+    row.insert("instance".to_string(), run.instance.clone().into());
+    row.insert("depth".to_string(), run.depth.into());
+    row.insert("solvers".to_string(), run.solvers.join(",").into());
+    row
+}
+
+/// The runs as CSV with a header line, one line per run. The hosts
+/// and the splits are left out, see splits_csv.
+pub fn csv(runs: &[RunStatistics]) -> String {
+    let rows: Vec<serde_json::Map<String, serde_json::Value>> = runs
+        .iter()
+        .map(|r| {
+            let mut row = run_key(r);
+            row.extend(
+                object(&r.statistics)
+                    .into_iter()
+                    .filter(|(k, _)| k != "hosts" && k != "splits"),
+            );
+            row
+        })
+        .collect();
+    csv_table(&rows)
+}
+
+/// The splits of the runs as CSV with a header line, one line per
+/// split, keyed by the instance, depth and solvers of their run.
+pub fn splits_csv(runs: &[RunStatistics]) -> String {
+    let rows: Vec<serde_json::Map<String, serde_json::Value>> = runs
+        .iter()
+        .flat_map(|r| {
+            r.statistics.splits.iter().map(|s| {
+                let mut row = run_key(r);
+                row.extend(object(s));
+                row
+            })
+        })
+        .collect();
+    csv_table(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv() {
+        let run = RunStatistics {
+            instance: "f.qdimacs".to_string(),
+            depth: 1,
+            solvers: vec!["caqe".to_string()],
+            statistics: SolveStatistics {
+                minimal_execution_time_seconds: 1.5,
+                minimal_execution_time_lower_seconds: 1.5,
+                minimal_execution_time_upper_seconds: Some(1.5),
+                summed_execution_time_seconds: 3.0,
+                non_split_execution_time_seconds: None,
+                speedup_against_non_split: None,
+                speedup_lower: None,
+                speedup_upper: None,
+                required_cores: 2,
+                result: SolverReturnCode::Sat,
+                naive_split_count: 2,
+                run_tasks_compared_to_naive: 1.0,
+                minimal_cpu_seconds: None,
+                summed_cpu_seconds: None,
+                non_split_cpu_seconds: None,
+                peak_memory_kb: None,
+                peak_memory_split: None,
+                hosts: vec![],
+                splits: vec![SplitStatistics {
+                    id: "0".to_string(),
+                    generation: 0,
+                    solver: "caqe".to_string(),
+                    probed: false,
+                    result: SolverReturnCode::Sat,
+                    wall_seconds: 1.5,
+                    cpu_seconds: None,
+                    peak_memory_kb: None,
+                    host: Some("n1".to_string()),
+                }],
+            },
+        };
+        let runs = csv(std::slice::from_ref(&run));
+        let lines: Vec<&str> = runs.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("instance,depth,solvers,minimal_execution_time_seconds,"));
+        assert!(lines[0].ends_with(",peak_memory_kb,peak_memory_split"));
+        assert!(lines[1].starts_with("f.qdimacs,1,caqe,1.5,1.5,1.5,3.0,,,,,2,Sat,"));
+
+        let splits = splits_csv(&[run]);
+        let lines: Vec<&str> = splits.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "instance,depth,solvers,id,generation,solver,probed,result,wall_seconds,cpu_seconds,peak_memory_kb,host",
+                "f.qdimacs,1,caqe,0,0,caqe,false,Sat,1.5,,,n1",
+            ]
+        );
+    }
+}
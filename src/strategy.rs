@@ -0,0 +1,220 @@
+use serde::Serialize;
+
+use crate::merge::{quant_from_prefix, reduce_splits, Quantifier, SplitNode};
+use crate::{Formula, SolverResult, SolverReturnCode, Split, SplitOptions};
+
+/// A branch of the decision tree of a winning strategy. At a layer of
+/// the winner, only the winning moves are kept, at a layer of the
+/// opponent all moves, as the winner has to answer every one of them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StrategyNode {
+    /// Quantifier of the split this branch assigns, None for the root.
+    pub quantifier: Option<Quantifier>,
+    /// Assignment of the split, empty for the root and expanded splits.
+    pub assignment: Vec<i32>,
+    pub result: SolverReturnCode,
+    /// Id of the split job, for the leaves of the split tree.
+    pub split: Option<String>,
+    /// Solver that decided the split job.
+    pub solver: Option<String>,
+    /// Outermost assignment printed by the solver, e.g. QDO "V" lines.
+    pub certificate: Vec<i32>,
+    pub children: Vec<StrategyNode>,
+}
+
+// Whether a child with the given result is part of the strategy of the
+// player winning its parent.
+fn decisive(quant: Quantifier, parent: SolverReturnCode, child: SolverReturnCode) -> bool {
+    match (quant, parent) {
+        (Quantifier::Exists, SolverReturnCode::Sat) => child == SolverReturnCode::Sat,
+        (Quantifier::Forall, SolverReturnCode::Unsat) => child == SolverReturnCode::Unsat,
+        _ => parent.is_decided(),
+    }
+}
+
+struct Builder<'a> {
+    formula: &'a Formula,
+    options: &'a SplitOptions,
+}
+
+impl Builder<'_> {
+    // The branches below the given splits, whose combinations are
+    // solved by the given nodes.
+    fn branches(&self, splits: &[Split], nodes: &[SplitNode]) -> Vec<StrategyNode> {
+        let results: Vec<SolverResult> = nodes
+            .iter()
+            .map(|n| n.resolved(self.formula, self.options))
+            .collect();
+        let layers = reduce_splits(self.formula, splits, results);
+        self.children(splits, nodes, &layers, 0, 0)
+    }
+
+    fn children(
+        &self,
+        splits: &[Split],
+        nodes: &[SplitNode],
+        layers: &[Vec<SolverResult>],
+        level: usize,
+        index: usize,
+    ) -> Vec<StrategyNode> {
+        if level == splits.len() {
+            let node = &nodes[index];
+            if node.result.result.is_decided() || node.children.is_empty() {
+                return vec![];
+            }
+            let splits = self
+                .formula
+                .generation_splits(self.options, node.generation + 1);
+            return self.branches(splits, &node.children);
+        }
+        let parent = layers[splits.len() - level][index].result;
+        let split = &splits[level];
+        let quant = quant_from_prefix(self.formula, split);
+        let layer = &layers[splits.len() - level - 1];
+        let width = split.nr_of_splits();
+        (0..width)
+            .map(|i| index * width + i)
+            .filter(|i| decisive(quant, parent, layer[*i].result))
+            .map(|i| {
+                let leaf = (level + 1 == splits.len()).then(|| &nodes[i]);
+                StrategyNode {
+                    quantifier: Some(quant),
+                    assignment: if split.is_expanded() {
+                        vec![]
+                    } else {
+                        split.assignments()[i % width].clone()
+                    },
+                    result: layer[i].result,
+                    split: leaf.map(|n| self.formula.split_id(self.options, n.generation, n.index)),
+                    solver: leaf.map(|n| n.result.name.clone()),
+                    certificate: leaf
+                        .map(|n| n.result.certificate.clone())
+                        .unwrap_or_default(),
+                    children: self.children(splits, nodes, layers, level + 1, i),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The decision tree of the winning strategy of the merged split
+/// trees, see StrategyNode. None if the formula is undecided.
+pub fn strategy(
+    formula: &Formula,
+    options: &SplitOptions,
+    nodes: &[SplitNode],
+) -> Option<StrategyNode> {
+    let builder = Builder { formula, options };
+    let splits = formula.generation_splits(options, 0);
+    let results: Vec<SolverResult> = nodes.iter().map(|n| n.resolved(formula, options)).collect();
+    let result = reduce_splits(formula, splits, results).pop().unwrap()[0].result;
+    if !result.is_decided() {
+        return None;
+    }
+    Some(StrategyNode {
+        quantifier: None,
+        assignment: vec![],
+        result,
+        split: None,
+        solver: None,
+        certificate: vec![],
+        children: builder.branches(splits, nodes),
+    })
+}
+
+impl StrategyNode {
+    /// The tree as indented text, one branch per line.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.write_text(&mut text, 0);
+        text
+    }
+
+    fn write_text(&self, text: &mut String, indent: usize) {
+        let lits = |lits: &[i32]| {
+            lits.iter()
+                .map(|l| l.to_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        };
+        text.push_str(&"  ".repeat(indent));
+        match self.quantifier {
+            Some(Quantifier::Exists) => text.push_str("exists "),
+            Some(Quantifier::Forall) => text.push_str("forall "),
+            None => {}
+        }
+        if self.quantifier.is_some() {
+            text.push_str(&lits(&self.assignment));
+            text.push_str(": ");
+        }
+        text.push_str(&self.result.to_string());
+        if let (Some(split), Some(solver)) = (&self.split, &self.solver) {
+            text.push_str(&format!(" (split {} by {}", split, solver));
+            if !self.certificate.is_empty() {
+                text.push_str(&format!(", V {}", lits(&self.certificate)));
+            }
+            text.push(')');
+        }
+        text.push('\n');
+        for c in self.children.iter() {
+            c.write_text(text, indent + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_qdimacs, JobInfo};
+
+    #[test]
+    fn test_strategy() {
+        let f = parse_qdimacs("p cnf 2 1\ne 1 0\na 2 0\n1 2 0\n", false).unwrap();
+        let options = SplitOptions {
+            depth: 2,
+            ..Default::default()
+        };
+        let node = |index: usize, result: SolverReturnCode| SplitNode {
+            generation: 0,
+            index,
+            result: SolverResult {
+                wall_seconds: 1.0,
+                result,
+                name: "test".to_string(),
+                cpu_seconds: None,
+                peak_memory_kb: None,
+                job: JobInfo::default(),
+                certificate: vec![1],
+                bounds: None,
+            },
+            children: vec![],
+            probed: false,
+        };
+        use SolverReturnCode::*;
+        let nodes = vec![node(0, Unsat), node(1, Sat), node(2, Sat), node(3, Sat)];
+
+        // Only 1 wins for the existential player, who then has to
+        // answer both moves of the universal player.
+        let s = strategy(&f, &options, &nodes).unwrap();
+        assert_eq!(s.result, Sat);
+        assert_eq!(s.children.len(), 1);
+        assert_eq!(s.children[0].assignment, vec![1]);
+        let answers = &s.children[0].children;
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[1].quantifier, Some(Quantifier::Forall));
+        assert_eq!(answers[1].split.as_deref(), Some("3"));
+        assert_eq!(
+            s.text(),
+            "Sat\n  exists 1: Sat\n    forall -2: Sat (split 2 by test, V 1)\n    \
+             forall 2: Sat (split 3 by test, V 1)\n"
+        );
+
+        let nodes = vec![
+            node(0, Timeout),
+            node(1, Sat),
+            node(2, Sat),
+            node(3, Timeout),
+        ];
+        assert_eq!(strategy(&f, &options, &nodes), None);
+    }
+}
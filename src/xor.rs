@@ -0,0 +1,173 @@
+use crate::{all_assignments, Formula, Split};
+
+/// Split on the parities of random XOR constraints. Each constraint
+/// defines a fresh parity variable through a Tseitin chain in the
+/// matrix, the branches are all assignments of the parity variables.
+#[derive(Debug, Clone)]
+pub struct XorSplit {
+    /// Variables of each XOR constraint.
+    pub xors: Vec<Vec<i32>>,
+    /// The parity variable of each XOR constraint.
+    pub vars: Vec<i32>,
+}
+
+impl XorSplit {
+    pub fn assignments(&self) -> Vec<Vec<i32>> {
+        all_assignments(&self.vars)
+    }
+}
+
+// SplitMix64, so that the same seed gives the same constraints on every
+// platform. Merging depends on reproducing the exact same split.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Formula {
+    fn new_var(&mut self) -> i32 {
+        self.nr_of_variables += 1;
+        self.nr_of_variables
+    }
+
+    // Adds clauses for out <-> (a xor b).
+    fn add_xor_definition(&mut self, out: i32, a: i32, b: i32) {
+        self.matrix.push(vec![-out, a, b]);
+        self.matrix.push(vec![-out, -a, -b]);
+        self.matrix.push(vec![out, -a, b]);
+        self.matrix.push(vec![out, a, -b]);
+    }
+
+    /// Adds a split on k random XOR constraints over the given
+    /// variables, producing 2^k splits. Without variables, all
+    /// variables of the matrix (DIMACS) or of the outermost quantifier
+    /// block (QBF) are used. The variables must be existential and in
+    /// the outermost quantifier block, so that the XOR constraints only
+    /// partition the outermost assignments.
+    pub fn add_xor_split(&mut self, k: usize, vars: &[i32], seed: u64) {
+        let outer: Vec<i32> = if self.prefix.is_empty() {
+            let mut v: Vec<i32> = self.matrix.iter().flatten().map(|l| l.abs()).collect();
+            v.sort_unstable();
+            v.dedup();
+            v
+        } else {
+            if self.prefix[0] > 0 {
+                panic!("XOR splits require an existential outermost quantifier block!");
+            }
+            self.prefix
+                .iter()
+                .take_while(|q| **q < 0)
+                .map(|q| q.abs())
+                .collect()
+        };
+        let vars: Vec<i32> = if vars.is_empty() {
+            outer.clone()
+        } else {
+            vars.to_vec()
+        };
+        if let Some(v) = vars.iter().find(|v| !outer.contains(v)) {
+            panic!(
+                "XOR split variable {} is not in the outermost existential block!",
+                v
+            );
+        }
+        if vars.is_empty() {
+            panic!("XOR splits require some variables!");
+        }
+
+        let mut rng = SplitMix64(seed);
+        let mut random_xor = || -> Vec<i32> {
+            let mut xor: Vec<i32> = vars
+                .iter()
+                .filter(|_| rng.next() & 1 == 1)
+                .copied()
+                .collect();
+            if xor.is_empty() {
+                xor.push(vars[(rng.next() % vars.len() as u64) as usize]);
+            }
+            xor
+        };
+        // Duplicate constraints would only produce contradicting splits,
+        // so they are redrawn a few times.
+        let mut xors: Vec<Vec<i32>> = vec![];
+        for _ in 0..k {
+            let mut xor = random_xor();
+            for _ in 0..16 {
+                if !xors.contains(&xor) {
+                    break;
+                }
+                xor = random_xor();
+            }
+            xors.push(xor);
+        }
+
+        let first_var = self.nr_of_variables + 1;
+        let mut parity_vars: Vec<i32> = vec![];
+        for xor in xors.iter() {
+            let mut acc = xor[0];
+            for x in xor[1..].iter() {
+                let t = self.new_var();
+                self.add_xor_definition(t, acc, *x);
+                acc = t;
+            }
+            if xor.len() == 1 {
+                let p = self.new_var();
+                self.matrix.push(vec![-p, acc]);
+                self.matrix.push(vec![p, -acc]);
+                acc = p;
+            }
+            parity_vars.push(acc);
+        }
+        self.nr_of_clauses = self.matrix.len() as i32;
+
+        // The new variables only depend on the outermost block.
+        if !self.prefix.is_empty() {
+            let pos = self.prefix.iter().take_while(|q| **q < 0).count();
+            let fresh: Vec<i32> = (first_var..=self.nr_of_variables).map(|v| -v).collect();
+            self.prefix.splice(pos..pos, fresh);
+        }
+
+        self.splits.insert(
+            0,
+            Split::Xor(XorSplit {
+                xors,
+                vars: parity_vars,
+            }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_qdimacs;
+    use crate::propagation::Propagator;
+
+    #[test]
+    fn test_xor_split_partitions() {
+        let mut f = parse_qdimacs("p cnf 3 1\n1 2 3 0\n", false).unwrap();
+        f.add_xor_split(2, &[], 7);
+        let splits = f.produce_splits(2);
+        assert_eq!(splits.len(), 4);
+
+        // Every model of the original matrix propagates to exactly one
+        // parity assignment.
+        let p = Propagator::new(&f);
+        for i in 1..8 {
+            let a: Vec<i32> = (1..=3)
+                .map(|v| if (i >> (v - 1)) & 1 == 1 { v } else { -v })
+                .collect();
+            let compatible = splits
+                .iter()
+                .filter(|s| p.propagate(&[a.clone(), s.to_vec()].concat()).is_some())
+                .count();
+            assert_eq!(compatible, 1);
+        }
+    }
+}